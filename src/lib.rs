@@ -1,85 +1,1012 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
 
-#[derive(Debug, Clone, PartialEq)]
+// Permission levels ordered from least to most restrictive
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Permission {
     Public,
+    Protected,
     Private,
 }
 
+// Anything usable as a node ID: `Eq + Hash + Clone` for the maps. Methods that return
+// sorted results also need `Ord`, and the text renderers need `Display`.
+pub trait NodeId: Eq + Hash + Clone {}
+
+impl<T: Eq + Hash + Clone> NodeId for T {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TreeError<Id = u32> {
+    NodeAlreadyExists(Id),
+    NodeNotFound(Id),
+    SelfParent(Id),
+    HasChildren(Id),
+    ChildAlreadyHasParent { child: Id, existing_parent: Id },
+    WouldCreateCycle { node: Id, target: Id },
+    InvalidFanout(usize),
+    InvalidDepth { id: Id, depth: usize },
+    Json(String),
+    DanglingChild { parent: Id, child: Id },
+    InconsistentParent { child: Id, parent: Id },
+    Cycle(Id),
+    MismatchedId { key: Id, id: Id },
+}
+
+impl<Id: fmt::Display> fmt::Display for TreeError<Id> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeError::NodeAlreadyExists(id) => write!(f, "Node with ID {} already exists", id),
+            TreeError::NodeNotFound(id) => write!(f, "Node with ID {} doesn't exist", id),
+            TreeError::SelfParent(id) => write!(f, "Node {} cannot be its own parent", id),
+            TreeError::HasChildren(id) => write!(f, "Node {} still has children", id),
+            TreeError::ChildAlreadyHasParent {
+                child,
+                existing_parent,
+            } => write!(
+                f,
+                "Node {} already has node {} as its parent",
+                child, existing_parent
+            ),
+            TreeError::WouldCreateCycle { node, target } => write!(
+                f,
+                "Placing node {} under node {} would create a cycle",
+                node, target
+            ),
+            TreeError::InvalidFanout(fanout) => write!(f, "Fanout {} is not allowed", fanout),
+            TreeError::InvalidDepth { id, depth } => {
+                write!(f, "Node {} can't be placed at depth {}", id, depth)
+            }
+            TreeError::Json(message) => write!(f, "Invalid tree JSON: {}", message),
+            TreeError::DanglingChild { parent, child } => {
+                write!(f, "Node {} lists missing node {} as a child", parent, child)
+            }
+            TreeError::InconsistentParent { child, parent } => write!(
+                f,
+                "Parent map and children disagree about node {} under node {}",
+                child, parent
+            ),
+            TreeError::Cycle(id) => write!(f, "Node {} is its own ancestor", id),
+            TreeError::MismatchedId { key, id } => {
+                write!(f, "Node stored under ID {} claims ID {}", key, id)
+            }
+        }
+    }
+}
+
+impl<Id: fmt::Debug + fmt::Display> std::error::Error for TreeError<Id> {}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        deserialize = "Id: Eq + Hash + serde::Deserialize<'de>, T: serde::Deserialize<'de>"
+    ))
+)]
+pub struct TreeNode<Id = u32, T = ()> {
+    pub id: Id,
+    pub permission: Permission, // Effective permission after inheritance
+    pub declared_permission: Permission, // Permission the node was given itself
+    pub own_tags: HashSet<String>, // Tags added to this node directly
+    pub inherited_tags: HashSet<String>, // Tags passed down from ancestors
+    pub children: HashSet<Id>,
+    pub data: T, // Caller-supplied payload, untouched by the tree's own logic
+}
+
+// Written out by hand because comparing the `children` sets needs `Id: Eq + Hash`
+impl<Id: Eq + Hash, T: PartialEq> PartialEq for TreeNode<Id, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.permission == other.permission
+            && self.declared_permission == other.declared_permission
+            && self.own_tags == other.own_tags
+            && self.inherited_tags == other.inherited_tags
+            && self.children == other.children
+            && self.data == other.data
+    }
+}
+
+impl<Id, T> TreeNode<Id, T> {
+    // Every tag that applies to this node, its own and inherited ones
+    pub fn effective_tags(&self) -> HashSet<String> {
+        self.own_tags.union(&self.inherited_tags).cloned().collect()
+    }
+}
+
+// The parts of a node `for_each_mut` lets a closure change. The ID and the tree structure
+// stay read-only, so the walk can't leave `children` and `parent_map` out of step.
+#[derive(Debug)]
+pub struct NodeMut<'a, Id = u32, T = ()> {
+    pub id: &'a Id,
+    pub declared_permission: &'a mut Permission,
+    pub own_tags: &'a mut HashSet<String>,
+    pub data: &'a mut T,
+}
+
+// One parent-child edge with the child's metadata, for bulk export
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdgeRecord<Id = u32> {
+    pub parent: Id,
+    pub child: Id,
+    pub permission: Permission, // The child's permission
+    pub tags: HashSet<String>,  // The child's effective tags
+}
+
+// Node counts per permission level for one depth level of the forest
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthAccess<Id = u32> {
+    pub public: usize,
+    pub protected: usize,
+    pub private: usize,
+    pub private_region_roots: Vec<Id>, // Private nodes whose parent isn't private or is missing
+}
+
+impl<Id> Default for DepthAccess<Id> {
+    fn default() -> Self {
+        DepthAccess {
+            public: 0,
+            protected: 0,
+            private: 0,
+            private_region_roots: Vec::new(),
+        }
+    }
+}
+
+// Access statistics for the whole forest, indexed by depth (roots are at depth 0)
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessReport<Id = u32> {
+    pub levels: Vec<DepthAccess<Id>>,
+}
+
+impl<Id> Default for AccessReport<Id> {
+    fn default() -> Self {
+        AccessReport { levels: Vec::new() }
+    }
+}
+
+// A recorded mutation that can be replayed onto another tree with `apply_ops`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TreeOp<Id = u32, T = ()> {
+    AddNode {
+        id: Id,
+        permission: Permission,
+        data: T,
+    },
+    RemoveNode(Id),
+    RemoveSubtree(Id),
+    Detach(Id),
+    SetPermission {
+        id: Id,
+        permission: Permission,
+    },
+    Connect {
+        parent: Id,
+        child: Id,
+    },
+    Move {
+        node: Id,
+        new_parent: Id,
+    },
+    AddTag {
+        id: Id,
+        tag: String,
+    },
+    RemoveTag {
+        id: Id,
+        tag: String,
+    },
+    SetData {
+        id: Id,
+        data: T,
+    },
+}
+
+// Recorded mutations plus the means to copy payloads into them, captured when logging is
+// enabled so that only logging trees need `T: Clone`
+#[derive(Debug)]
+struct OpLog<Id, T> {
+    ops: Vec<TreeOp<Id, T>>,
+    clone_data: fn(&T) -> T,
+}
+
+// What `connect_nodes` does when the child already has a parent
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReconnectPolicy {
+    Reject, // Leave the tree unchanged and return `Ok`
+    #[default]
+    Error, // Leave the tree unchanged and return `ChildAlreadyHasParent`
+    Reparent, // Detach the child from its old parent and attach it to the new one
+}
+
+// Effective permission and inherited tags of one node, saved so an undo can put back
+// exactly what inheritance had produced before
+type InheritedState<Id> = (Id, Permission, HashSet<String>);
+
+// An entry in the undo/redo history. Applying an edit returns the edit that reverses it.
 #[derive(Debug)]
-pub struct TreeNode {
-    pub id: u32,
-    pub permission: Permission,
-    pub children: HashSet<u32>,
+enum Edit<Id, T> {
+    Insert {
+        node: TreeNode<Id, T>,
+        parent: Option<Id>,
+    }, // Put back a removed leaf
+    Delete(Id), // Remove a leaf
+    Relink {
+        node: Id,
+        parent: Option<Id>, // `None` makes `node` a root
+        states: Vec<InheritedState<Id>>,
+    }, // Hang a subtree somewhere else and restore its inherited state
 }
 
+// With the `serde` feature, `parent_map` is serialized as-is next to the `children` sets
+// rather than rebuilt on load, so the two must be kept consistent by whoever edits the
+// data. The op log and undo history are never serialized; a loaded tree starts with
+// logging disabled and nothing to undo.
 #[derive(Debug)]
-pub struct Tree {
-    pub nodes: HashMap<u32, TreeNode>,
-    pub parent_map: HashMap<u32, u32>, // Keeps track of parent-child relationships
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        deserialize = "Id: Eq + Hash + serde::Deserialize<'de>, T: serde::Deserialize<'de>"
+    ))
+)]
+pub struct Tree<Id = u32, T = ()> {
+    pub nodes: HashMap<Id, TreeNode<Id, T>>,
+    pub parent_map: HashMap<Id, Id>, // Keeps track of parent-child relationships
+    #[cfg_attr(feature = "serde", serde(default))]
+    reconnect_policy: ReconnectPolicy,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    op_log: Option<OpLog<Id, T>>, // Recorded mutations, if logging is enabled
+    #[cfg_attr(feature = "serde", serde(skip))]
+    undo_stack: Vec<Edit<Id, T>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    redo_stack: Vec<Edit<Id, T>>,
+}
+
+impl<Id: NodeId, T> Default for Tree<Id, T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl Tree {
+impl<Id: NodeId, T> Tree<Id, T> {
     pub fn new() -> Self {
         Tree {
             nodes: HashMap::new(),
             parent_map: HashMap::new(),
+            reconnect_policy: ReconnectPolicy::default(),
+            op_log: None,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+        }
+    }
+
+    // Number of nodes in the tree
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // Whether a node with this ID is in the tree
+    pub fn exists(&self, id: Id) -> bool {
+        self.nodes.contains_key(&id)
+    }
+
+    // The effective permission stored on `id`
+    pub fn get_permission(&self, id: Id) -> Option<Permission> {
+        self.nodes.get(&id).map(|node| node.permission.clone())
+    }
+
+    // Start recording mutations as the operations listed in `TreeOp`. Bulk helpers such as
+    // `flatten_chain`, `collapse_single_child_chains`, `for_each_mut` and `merge` are
+    // recorded as the equivalent sequence of single-node ops. Only edits through
+    // `get_data_mut` and direct changes to `nodes` or `parent_map` bypass the log; use
+    // `set_data` instead. Undo and redo are disabled while logging.
+    pub fn enable_op_log(&mut self)
+    where
+        T: Clone,
+    {
+        if self.op_log.is_none() {
+            self.op_log = Some(OpLog {
+                ops: Vec::new(),
+                clone_data: T::clone,
+            });
+        }
+    }
+
+    // Hand over the mutations recorded so far, leaving the log empty
+    pub fn take_ops(&mut self) -> Vec<TreeOp<Id, T>> {
+        self.op_log
+            .as_mut()
+            .map(|log| std::mem::take(&mut log.ops))
+            .unwrap_or_default()
+    }
+
+    // Replay recorded mutations in order, stopping at the first one that fails
+    pub fn apply_ops(&mut self, ops: &[TreeOp<Id, T>]) -> Result<(), TreeError<Id>>
+    where
+        T: Clone,
+    {
+        for op in ops {
+            match op {
+                TreeOp::AddNode {
+                    id,
+                    permission,
+                    data,
+                } => self.add_node(id.clone(), permission.clone(), data.clone())?,
+                TreeOp::RemoveNode(id) => self.remove_node(id.clone())?,
+                TreeOp::RemoveSubtree(root) => {
+                    self.remove_subtree(root.clone())?;
+                }
+                TreeOp::Detach(id) => self.detach_node(id.clone())?,
+                TreeOp::SetPermission { id, permission } => {
+                    self.set_permission(id.clone(), permission.clone())?
+                }
+                TreeOp::Connect { parent, child } => {
+                    self.connect_nodes(parent.clone(), child.clone())?
+                }
+                TreeOp::Move { node, new_parent } => {
+                    self.move_subtree(node.clone(), new_parent.clone())?
+                }
+                TreeOp::AddTag { id, tag } => self.add_tag_to_node(id.clone(), tag)?,
+                TreeOp::RemoveTag { id, tag } => self.remove_tag_from_node(id.clone(), tag)?,
+                TreeOp::SetData { id, data } => self.set_data(id.clone(), data.clone())?,
+            }
+        }
+        Ok(())
+    }
+
+    // Append `op` to the log when logging is enabled
+    fn record(&mut self, op: TreeOp<Id, T>) {
+        if let Some(log) = self.op_log.as_mut() {
+            log.ops.push(op);
+        }
+    }
+
+    // Copy a payload for the log, or `None` when logging is disabled
+    fn logged_data(&self, data: &T) -> Option<T> {
+        self.op_log.as_ref().map(|log| (log.clone_data)(data))
+    }
+
+    // Ops that rebuild this whole tree in an empty one, adding every parent before its
+    // children
+    fn rebuild_ops(&self, clone_data: fn(&T) -> T) -> Vec<TreeOp<Id, T>> {
+        let mut ops = Vec::new();
+        for root in self.root_ids() {
+            for id in self.subtree_ids(&root) {
+                let node = &self.nodes[&id];
+                ops.push(TreeOp::AddNode {
+                    id: id.clone(),
+                    permission: node.declared_permission.clone(),
+                    data: clone_data(&node.data),
+                });
+                let mut tags: Vec<&String> = node.own_tags.iter().collect();
+                tags.sort();
+                for tag in tags {
+                    ops.push(TreeOp::AddTag {
+                        id: id.clone(),
+                        tag: tag.clone(),
+                    });
+                }
+                if let Some(parent_id) = self.parent_map.get(&id) {
+                    ops.push(TreeOp::Connect {
+                        parent: parent_id.clone(),
+                        child: id,
+                    });
+                }
+            }
+        }
+        ops
+    }
+
+    // Reverse the last `add_node`, `connect_nodes`, `move_subtree` or `remove_node`,
+    // returning whether there was anything to undo. Any other mutation clears the history,
+    // since the saved state would no longer match the tree. Undo and redo are disabled while
+    // the op log is enabled, because a replica replaying the log couldn't follow them.
+    pub fn undo(&mut self) -> bool {
+        if self.op_log.is_some() {
+            return false;
+        }
+        match self.undo_stack.pop() {
+            Some(edit) => {
+                let reverse = self.apply_edit(edit);
+                self.redo_stack.push(reverse);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Reapply the last undone mutation, returning whether there was anything to redo
+    pub fn redo(&mut self) -> bool {
+        if self.op_log.is_some() {
+            return false;
+        }
+        match self.redo_stack.pop() {
+            Some(edit) => {
+                let reverse = self.apply_edit(edit);
+                self.undo_stack.push(reverse);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Remember how to reverse a mutation that was just made; it replaces anything undone
+    fn push_undo(&mut self, edit: Edit<Id, T>) {
+        self.undo_stack.push(edit);
+        self.redo_stack.clear();
+    }
+
+    // Drop the undo history after a mutation it can't reverse
+    fn forget_history(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+
+    // Effective permission and inherited tags of every node in the subtree under `root`
+    fn inherited_states(&self, root: &Id) -> Vec<InheritedState<Id>> {
+        self.subtree_ids(root)
+            .into_iter()
+            .map(|id| {
+                let node = &self.nodes[&id];
+                let (permission, tags) = (node.permission.clone(), node.inherited_tags.clone());
+                (id, permission, tags)
+            })
+            .collect()
+    }
+
+    fn apply_edit(&mut self, edit: Edit<Id, T>) -> Edit<Id, T> {
+        match edit {
+            Edit::Insert { node, parent } => {
+                let id = node.id.clone();
+                if let Some(parent_id) = &parent {
+                    if let Some(parent_node) = self.nodes.get_mut(parent_id) {
+                        parent_node.children.insert(id.clone());
+                    }
+                    self.parent_map.insert(id.clone(), parent_id.clone());
+                }
+                self.nodes.insert(id.clone(), node);
+                Edit::Delete(id)
+            }
+            Edit::Delete(id) => {
+                let parent = self.parent_map.remove(&id);
+                if let Some(parent_node) = parent.as_ref().and_then(|p| self.nodes.get_mut(p)) {
+                    parent_node.children.remove(&id);
+                }
+                let node = self
+                    .nodes
+                    .remove(&id)
+                    .expect("undo history matches the tree");
+                Edit::Insert { node, parent }
+            }
+            Edit::Relink {
+                node,
+                parent,
+                states,
+            } => {
+                let reverse = Edit::Relink {
+                    node: node.clone(),
+                    parent: self.parent_map.get(&node).cloned(),
+                    states: self.inherited_states(&node),
+                };
+
+                if let Some(old_parent_id) = self.parent_map.remove(&node) {
+                    if let Some(old_parent) = self.nodes.get_mut(&old_parent_id) {
+                        old_parent.children.remove(&node);
+                    }
+                }
+                if let Some(parent_id) = parent {
+                    if let Some(parent_node) = self.nodes.get_mut(&parent_id) {
+                        parent_node.children.insert(node.clone());
+                    }
+                    self.parent_map.insert(node, parent_id);
+                }
+
+                for (id, permission, inherited_tags) in states {
+                    if let Some(node) = self.nodes.get_mut(&id) {
+                        node.permission = permission;
+                        node.inherited_tags = inherited_tags;
+                    }
+                }
+                reverse
+            }
+        }
+    }
+
+    // Build a tree from (depth, id, permission) entries listed in pre-order. Each entry's
+    // parent is the closest earlier entry one level up, and depth 0 starts a new root.
+    // Every node gets `T::default()` as its payload.
+    pub fn from_preorder(entries: &[(usize, Id, Permission)]) -> Result<Tree<Id, T>, TreeError<Id>>
+    where
+        T: Default,
+    {
+        let mut tree = Tree::new();
+        let mut path: Vec<Id> = Vec::new(); // IDs from the current root down to the last entry
+
+        for (depth, id, permission) in entries {
+            let depth = *depth;
+            // A node can go at most one level below the previous entry
+            if depth > path.len() {
+                return Err(TreeError::InvalidDepth {
+                    id: id.clone(),
+                    depth,
+                });
+            }
+
+            path.truncate(depth);
+            tree.add_node(id.clone(), permission.clone(), T::default())?;
+            if let Some(parent_id) = path.last() {
+                tree.connect_nodes(parent_id.clone(), id.clone())?;
+            }
+            path.push(id.clone());
+        }
+
+        // The construction itself isn't something to undo
+        tree.forget_history();
+        Ok(tree)
+    }
+
+    // Build a tree from (id, parent, permission) triples, where roots have no parent.
+    // Every node gets `T::default()` as its payload.
+    pub fn from_parent_array(
+        parents: &[(Id, Option<Id>, Permission)],
+    ) -> Result<Tree<Id, T>, TreeError<Id>>
+    where
+        T: Default,
+    {
+        let mut tree = Tree::new();
+        for (id, _, permission) in parents {
+            tree.add_node(id.clone(), permission.clone(), T::default())?;
+        }
+
+        for (id, parent, _) in parents {
+            if let Some(parent_id) = parent {
+                tree.connect_nodes(parent_id.clone(), id.clone())?;
+            }
+        }
+
+        tree.forget_history();
+        Ok(tree)
+    }
+
+    // Check that the tree is internally consistent: every node's ID matches its key, every
+    // listed child exists, `children` and `parent_map` agree (so each non-root node has
+    // exactly one parent), and no node is its own ancestor. Reports the first problem found.
+    pub fn validate(&self) -> Result<(), TreeError<Id>> {
+        for (key, node) in &self.nodes {
+            if node.id != *key {
+                return Err(TreeError::MismatchedId {
+                    key: key.clone(),
+                    id: node.id.clone(),
+                });
+            }
+        }
+
+        for (parent, node) in &self.nodes {
+            for child in &node.children {
+                if !self.nodes.contains_key(child) {
+                    return Err(TreeError::DanglingChild {
+                        parent: parent.clone(),
+                        child: child.clone(),
+                    });
+                }
+                // Also catches a child listed under more than one parent
+                if self.parent_map.get(child) != Some(parent) {
+                    return Err(TreeError::InconsistentParent {
+                        child: child.clone(),
+                        parent: parent.clone(),
+                    });
+                }
+            }
+        }
+
+        for (child, parent) in &self.parent_map {
+            let listed = self
+                .nodes
+                .get(parent)
+                .is_some_and(|node| node.children.contains(child));
+            if !self.nodes.contains_key(child) || !listed {
+                return Err(TreeError::InconsistentParent {
+                    child: child.clone(),
+                    parent: parent.clone(),
+                });
+            }
+        }
+
+        // A walk up from any node must reach a root within `len` steps
+        for id in self.nodes.keys() {
+            let mut current_id = id;
+            for _ in 0..=self.nodes.len() {
+                match self.parent_map.get(current_id) {
+                    Some(parent_id) if parent_id == id => return Err(TreeError::Cycle(id.clone())),
+                    Some(parent_id) => current_id = parent_id,
+                    None => break,
+                }
+            }
         }
+
+        Ok(())
+    }
+
+    // Choose how `connect_nodes` treats a child that already has a parent
+    pub fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) {
+        self.reconnect_policy = policy;
     }
 
-    // Add a node with permission to the tree
-    pub fn add_node(&mut self, id: u32, permission: Permission) {
+    // Add a node with permission and payload to the tree
+    pub fn add_node(
+        &mut self,
+        id: Id,
+        permission: Permission,
+        data: T,
+    ) -> Result<(), TreeError<Id>> {
         if self.nodes.contains_key(&id) {
-            println!("Node with ID {} already exists", id);
-            return;
+            return Err(TreeError::NodeAlreadyExists(id));
         }
+        let logged = self.logged_data(&data);
         self.nodes.insert(
-            id,
+            id.clone(),
             TreeNode {
-                id,
+                id: id.clone(),
                 permission: permission.clone(),
+                declared_permission: permission.clone(),
+                own_tags: HashSet::new(),
+                inherited_tags: HashSet::new(),
                 children: HashSet::new(),
+                data,
             },
         );
-        println!("Node with ID {} added with {:?} permission", id, permission);
+        self.push_undo(Edit::Delete(id.clone()));
+        if let Some(data) = logged {
+            self.record(TreeOp::AddNode {
+                id,
+                permission,
+                data,
+            });
+        }
+        Ok(())
+    }
+
+    // Borrow the payload stored on `id`
+    pub fn get_data(&self, id: Id) -> Option<&T> {
+        self.nodes.get(&id).map(|node| &node.data)
+    }
+
+    // Edits made through this reference bypass the op log; use `set_data` on a logging tree
+    pub fn get_data_mut(&mut self, id: Id) -> Option<&mut T> {
+        self.nodes.get_mut(&id).map(|node| &mut node.data)
+    }
+
+    // Replace the payload stored on `id`
+    pub fn set_data(&mut self, id: Id, data: T) -> Result<(), TreeError<Id>> {
+        if !self.nodes.contains_key(&id) {
+            return Err(TreeError::NodeNotFound(id));
+        }
+        let logged = self.logged_data(&data);
+        self.nodes.get_mut(&id).unwrap().data = data;
+        if let Some(data) = logged {
+            self.record(TreeOp::SetData { id, data });
+        }
+        Ok(())
+    }
+
+    // Remove a leaf node, detaching it from its parent if it has one
+    pub fn remove_node(&mut self, id: Id) -> Result<(), TreeError<Id>> {
+        let Some(node) = self.nodes.get(&id) else {
+            return Err(TreeError::NodeNotFound(id));
+        };
+
+        // Refuse to orphan a subtree
+        if !node.children.is_empty() {
+            return Err(TreeError::HasChildren(id));
+        }
+
+        let parent = self.parent_map.remove(&id);
+        if let Some(parent_id) = &parent {
+            if let Some(parent_node) = self.nodes.get_mut(parent_id) {
+                parent_node.children.remove(&id);
+            }
+        }
+        if let Some(node) = self.nodes.remove(&id) {
+            self.push_undo(Edit::Insert { node, parent });
+        }
+
+        self.record(TreeOp::RemoveNode(id));
+        Ok(())
+    }
+
+    // Remove `root` and everything below it, returning how many nodes were removed
+    pub fn remove_subtree(&mut self, root: Id) -> Result<usize, TreeError<Id>> {
+        if !self.nodes.contains_key(&root) {
+            return Err(TreeError::NodeNotFound(root));
+        }
+
+        // Detach the subtree from the rest of the tree; a root has nothing to detach from
+        if let Some(parent_id) = self.parent_map.remove(&root) {
+            if let Some(parent_node) = self.nodes.get_mut(&parent_id) {
+                parent_node.children.remove(&root);
+            }
+        }
+
+        let mut removed = 0;
+        let mut stack = vec![root.clone()];
+        while let Some(id) = stack.pop() {
+            if let Some(node) = self.nodes.remove(&id) {
+                self.parent_map.remove(&id);
+                stack.extend(node.children);
+                removed += 1;
+            }
+        }
+
+        self.forget_history();
+        self.record(TreeOp::RemoveSubtree(root));
+        Ok(removed)
+    }
+
+    // Disconnect `id` from its parent so its subtree becomes a separate tree. Permissions and
+    // inherited tags are left as they are: nodes made private by the old parent stay private
+    // after detaching.
+    pub fn detach_node(&mut self, id: Id) -> Result<(), TreeError<Id>> {
+        if !self.nodes.contains_key(&id) {
+            return Err(TreeError::NodeNotFound(id));
+        }
+
+        // Detaching a root changes nothing
+        if let Some(parent_id) = self.parent_map.remove(&id) {
+            if let Some(parent_node) = self.nodes.get_mut(&parent_id) {
+                parent_node.children.remove(&id);
+            }
+        }
+        self.forget_history();
+        self.record(TreeOp::Detach(id));
+        Ok(())
+    }
+
+    // Declare a new permission for `id` and recompute its subtree, which can also turn
+    // descendants that were only private by inheritance back to public
+    pub fn set_permission(&mut self, id: Id, permission: Permission) -> Result<(), TreeError<Id>> {
+        let Some(node) = self.nodes.get_mut(&id) else {
+            return Err(TreeError::NodeNotFound(id));
+        };
+        node.declared_permission = permission.clone();
+        self.update_permission(&id);
+
+        self.forget_history();
+        self.record(TreeOp::SetPermission { id, permission });
+        Ok(())
     }
 
     // Connect two nodes, making `parent_id` the parent of `child_id`
-    pub fn connect_nodes(&mut self, parent_id: u32, child_id: u32) {
-        if !self.nodes.contains_key(&parent_id) || !self.nodes.contains_key(&child_id) {
-            println!("Either parent or child node doesn't exist");
-            return;
+    pub fn connect_nodes(&mut self, parent_id: Id, child_id: Id) -> Result<(), TreeError<Id>> {
+        for id in [&parent_id, &child_id] {
+            if !self.nodes.contains_key(id) {
+                return Err(TreeError::NodeNotFound(id.clone()));
+            }
         }
 
         // Check if the parent ID and child ID are the same
         if parent_id == child_id {
-            println!("A node cannot be its own parent");
-            return;
+            return Err(TreeError::SelfParent(child_id));
         }
 
         // Check if the child already has a parent
-        if self.parent_map.contains_key(&child_id) {
-            println!("Node {} already has a parent", child_id);
-            return;
+        if let Some(existing_parent) = self.parent_map.get(&child_id) {
+            return match self.reconnect_policy {
+                ReconnectPolicy::Error => Err(TreeError::ChildAlreadyHasParent {
+                    existing_parent: existing_parent.clone(),
+                    child: child_id,
+                }),
+                ReconnectPolicy::Reject => Ok(()),
+                ReconnectPolicy::Reparent => self.move_subtree(child_id, parent_id),
+            };
+        }
+
+        // Linking a node under its own descendant would close a loop
+        if self.is_descendant(child_id.clone(), parent_id.clone()) {
+            return Err(TreeError::WouldCreateCycle {
+                node: child_id,
+                target: parent_id,
+            });
         }
 
+        let undo = Edit::Relink {
+            node: child_id.clone(),
+            parent: None,
+            states: self.inherited_states(&child_id),
+        };
         if let Some(parent_node) = self.nodes.get_mut(&parent_id) {
-            parent_node.children.insert(child_id);
-            self.parent_map.insert(child_id, parent_id);
-            println!("Node {} connected as child of {}", child_id, parent_id);
+            parent_node.children.insert(child_id.clone());
+            self.parent_map.insert(child_id.clone(), parent_id.clone());
+        }
+
+        // The child may be the root of an existing subtree, so recompute
+        // the permission and tags of every descendant, not just the child
+        self.update_permission(&child_id);
+        self.update_tags(&child_id);
+        self.push_undo(undo);
+        self.record(TreeOp::Connect {
+            parent: parent_id,
+            child: child_id,
+        });
+        Ok(())
+    }
+
+    // List the ancestors of `id`, from its parent up to the root
+    pub fn get_ancestors(&self, id: Id) -> Vec<Id> {
+        let mut ancestors = Vec::new();
+        let mut current_id = &id;
+        while let Some(parent_id) = self.parent_map.get(current_id) {
+            ancestors.push(parent_id.clone());
+            current_id = parent_id;
+        }
+        ancestors
+    }
+
+    // List the direct children of `id` in ascending order
+    pub fn get_children(&self, id: Id) -> Vec<Id>
+    where
+        Id: Ord,
+    {
+        let mut children: Vec<Id> = self
+            .nodes
+            .get(&id)
+            .map(|node| node.children.iter().cloned().collect())
+            .unwrap_or_default();
+        children.sort();
+        children
+    }
+
+    // List the other children of `id`'s parent in ascending order; a root has no siblings
+    pub fn get_siblings(&self, id: Id) -> Vec<Id>
+    where
+        Id: Ord,
+    {
+        match self.parent_map.get(&id) {
+            Some(parent_id) => {
+                let mut siblings = self.get_children(parent_id.clone());
+                siblings.retain(|sibling_id| *sibling_id != id);
+                siblings
+            }
+            None => Vec::new(),
+        }
+    }
+
+    // List every node below `id` in breadth-first order, visiting each node's children
+    // by ascending ID
+    pub fn get_descendants(&self, id: Id) -> Vec<Id>
+    where
+        Id: Ord,
+    {
+        let mut descendants = Vec::new();
+        let mut queue = VecDeque::from([id]);
+        while let Some(current_id) = queue.pop_front() {
+            let children = self.get_children(current_id);
+            descendants.extend(children.iter().cloned());
+            queue.extend(children);
+        }
+        descendants
+    }
+
+    // Lazily walk the subtree under `root` level by level, starting with `root` itself
+    // and visiting each node's children by ascending ID. Yields nothing if `root` is missing.
+    pub fn bfs(&self, root: Id) -> impl Iterator<Item = Id> + '_
+    where
+        Id: Ord,
+    {
+        let mut queue = VecDeque::new();
+        if self.nodes.contains_key(&root) {
+            queue.push_back(root);
+        }
+        std::iter::from_fn(move || {
+            let id = queue.pop_front()?;
+            queue.extend(self.get_children(id.clone()));
+            Some(id)
+        })
+    }
+
+    // Lazily walk the subtree under `root` in pre-order, visiting each node's children by
+    // ascending ID. Uses an explicit stack, so deep trees don't grow the call stack.
+    pub fn dfs(&self, root: Id) -> impl Iterator<Item = Id> + '_
+    where
+        Id: Ord,
+    {
+        let mut stack = Vec::new();
+        if self.nodes.contains_key(&root) {
+            stack.push(root);
+        }
+        std::iter::from_fn(move || {
+            let id = stack.pop()?;
+            // Push in reverse so the smallest child is popped first
+            stack.extend(self.get_children(id.clone()).into_iter().rev());
+            Some(id)
+        })
+    }
+
+    // Work out a node's permission from the declared permissions of the node and its
+    // ancestors, without relying on the stored effective permission. The most
+    // restrictive declared level wins.
+    pub fn effective_permission(&self, id: Id) -> Option<Permission> {
+        if !self.nodes.contains_key(&id) {
+            return None;
+        }
+
+        self.get_ancestors(id.clone())
+            .into_iter()
+            .chain(std::iter::once(id))
+            .map(|id| self.nodes[&id].declared_permission.clone())
+            .max()
+    }
+
+    // Count the edges between `id` and its root, so a root is at depth 0
+    pub fn depth(&self, id: Id) -> Option<usize> {
+        if !self.nodes.contains_key(&id) {
+            return None;
+        }
+
+        let mut depth = 0;
+        let mut current_id = &id;
+        while let Some(parent_id) = self.parent_map.get(current_id) {
+            depth += 1;
+            current_id = parent_id;
+        }
+        Some(depth)
+    }
+
+    // Find the deepest node that is an ancestor of both `a` and `b`, counting each node as its
+    // own ancestor. Returns `None` for unknown nodes or nodes in separate trees.
+    pub fn lowest_common_ancestor(&self, a: Id, b: Id) -> Option<Id> {
+        if !self.nodes.contains_key(&a) || !self.nodes.contains_key(&b) {
+            return None;
         }
 
-        // Ensure the child inherits permission if the parent is private
-        self.update_permission(child_id);
+        let mut a_chain: HashSet<Id> = self.get_ancestors(a.clone()).into_iter().collect();
+        a_chain.insert(a);
+
+        std::iter::once(b.clone())
+            .chain(self.get_ancestors(b))
+            .find(|id| a_chain.contains(id))
+    }
+
+    // List the nodes from `a` up to their lowest common ancestor and back down to `b`,
+    // including both ends. Returns `None` if the nodes aren't in the same tree.
+    pub fn path_between(&self, a: Id, b: Id) -> Option<Vec<Id>> {
+        let lca = self.lowest_common_ancestor(a.clone(), b.clone())?;
+
+        // Walk from `id` up to the common ancestor, including both
+        let climb = |id: Id| {
+            let mut path = vec![id.clone()];
+            let mut current_id = id;
+            while current_id != lca {
+                current_id = self.parent_map[&current_id].clone();
+                path.push(current_id.clone());
+            }
+            path
+        };
+
+        let mut path = climb(a);
+        let mut down = climb(b);
+        down.pop(); // The common ancestor is already on the way up
+        down.reverse();
+        path.extend(down);
+        Some(path)
     }
 
-    pub fn is_descendant(&self, node_id: u32, potential_descendant_id: u32) -> bool {
+    pub fn is_descendant(&self, node_id: Id, potential_descendant_id: Id) -> bool {
         // Start with the potential descendant
-        let mut current_id = potential_descendant_id;
+        let mut current_id = &potential_descendant_id;
 
         // Traverse up the parent chain
-        while let Some(&parent_id) = self.parent_map.get(&current_id) {
-            if parent_id == node_id {
+        while let Some(parent_id) = self.parent_map.get(current_id) {
+            if *parent_id == node_id {
                 return true; // Found the ancestor
             }
             current_id = parent_id;
@@ -89,85 +1016,1265 @@ impl Tree {
     }
 
     // Move a subtree rooted at `node_id` under `new_parent_id`
-    pub fn move_subtree(&mut self, node_id: u32, new_parent_id: u32) {
-        if !self.nodes.contains_key(&node_id) || !self.nodes.contains_key(&new_parent_id) {
-            println!("Either node or new parent doesn't exist");
-            return;
-        }
-
-        // Prevent moving a node into its own subtree
-        if self.is_descendant(node_id, new_parent_id) {
-            println!("Cannot move a node into its own subtree");
-            return;
-        }
+    pub fn move_subtree(&mut self, node_id: Id, new_parent_id: Id) -> Result<(), TreeError<Id>> {
+        self.can_move(node_id.clone(), new_parent_id.clone())?;
+        let undo = Edit::Relink {
+            node: node_id.clone(),
+            parent: self.parent_map.get(&node_id).cloned(),
+            states: self.inherited_states(&node_id),
+        };
 
         // Find the current parent of `node_id`
-        if let Some(&current_parent_id) = self.parent_map.get(&node_id) {
+        if let Some(current_parent_id) = self.parent_map.get(&node_id) {
             // Remove node_id from current parent's children
-            if let Some(current_parent) = self.nodes.get_mut(&current_parent_id) {
+            if let Some(current_parent) = self.nodes.get_mut(current_parent_id) {
                 current_parent.children.remove(&node_id);
             }
         }
 
         // Move the node to the new parent
         if let Some(new_parent_node) = self.nodes.get_mut(&new_parent_id) {
-            new_parent_node.children.insert(node_id);
+            new_parent_node.children.insert(node_id.clone());
         }
-        self.parent_map.insert(node_id, new_parent_id);
+        self.parent_map
+            .insert(node_id.clone(), new_parent_id.clone());
 
-        // Update permissions for the subtree based on the new parent
-        self.update_permission(node_id);
+        // Update permissions and tags for the subtree based on the new parent
+        self.update_permission(&node_id);
+        self.update_tags(&node_id);
 
-        println!(
-            "Moved subtree rooted at node {} to new parent node {}",
-            node_id, new_parent_id
-        );
+        self.push_undo(undo);
+        self.record(TreeOp::Move {
+            node: node_id,
+            new_parent: new_parent_id,
+        });
+        Ok(())
     }
 
-    // Recursively update the permission of a node and its subtree
-    fn update_permission(&mut self, node_id: u32) {
-        if let Some(node) = self.nodes.get(&node_id) {
-            // If this node is private, its entire subtree must be private
-            if node.permission == Permission::Private {
-                // No need to continue if this node is private
-                return;
+    // Check whether `move_subtree(node_id, new_parent_id)` would succeed, without moving anything
+    pub fn can_move(&self, node_id: Id, new_parent_id: Id) -> Result<(), TreeError<Id>> {
+        for id in [&node_id, &new_parent_id] {
+            if !self.nodes.contains_key(id) {
+                return Err(TreeError::NodeNotFound(id.clone()));
             }
         }
 
-        if let Some(&parent_id) = self.parent_map.get(&node_id) {
-            if let Some(parent_node) = self.nodes.get(&parent_id) {
-                // If parent is private, make the node private as well
-                if parent_node.permission == Permission::Private {
-                    if let Some(node) = self.nodes.get_mut(&node_id) {
-                        node.permission = Permission::Private;
-                    }
-                }
-            }
+        if node_id == new_parent_id {
+            return Err(TreeError::SelfParent(node_id));
         }
 
-        // Recursively update permission of all children
-        if let Some(node) = self.nodes.get(&node_id) {
-            for child_id in node.children.clone() {
-                self.update_permission(child_id);
-            }
+        // Prevent moving a node into its own subtree
+        if self.is_descendant(node_id.clone(), new_parent_id.clone()) {
+            return Err(TreeError::WouldCreateCycle {
+                node: node_id,
+                target: new_parent_id,
+            });
         }
+
+        Ok(())
     }
 
-    pub fn print_tree(&self, root: u32, indent: usize) -> String {
-        let mut result = String::new();
+    // Recompute the effective permission of a node and its subtree, parents before
+    // children. A node's effective permission is the more restrictive of its declared
+    // permission and its parent's effective permission. Uses an explicit stack so long
+    // chains can't overflow the call stack.
+    fn update_permission(&mut self, node_id: &Id) {
+        let mut stack = vec![node_id.clone()];
+        while let Some(current_id) = stack.pop() {
+            let inherited = self
+                .parent_map
+                .get(&current_id)
+                .and_then(|parent_id| self.nodes.get(parent_id))
+                .map(|parent| parent.permission.clone());
 
-        if let Some(node) = self.nodes.get(&root) {
-            result.push_str(&format!(
-                "{:indent$}- node {} ({:?})",
-                "",
-                node.id,
-                node.permission,
-                indent = indent
-            ));
-            for &child in &node.children {
-                result.push_str(&self.print_tree(child, indent + 4));
+            if let Some(node) = self.nodes.get_mut(&current_id) {
+                node.permission = match inherited {
+                    Some(inherited) => inherited.max(node.declared_permission.clone()),
+                    None => node.declared_permission.clone(),
+                };
+                stack.extend(node.children.iter().cloned());
             }
         }
-        result
+    }
+
+    // Add a tag to a node, passing it down to the node's whole subtree
+    pub fn add_tag_to_node(&mut self, id: Id, tag: &str) -> Result<(), TreeError<Id>> {
+        let Some(node) = self.nodes.get_mut(&id) else {
+            return Err(TreeError::NodeNotFound(id));
+        };
+        node.own_tags.insert(tag.to_string());
+        self.update_tags(&id);
+
+        self.forget_history();
+        self.record(TreeOp::AddTag {
+            id,
+            tag: tag.to_string(),
+        });
+        Ok(())
+    }
+
+    // Remove a tag the node declared itself and recompute its subtree. Descendants keep the
+    // tag if they declared it too or another ancestor still provides it; a tag the node
+    // only inherits can't be removed here.
+    pub fn remove_tag_from_node(&mut self, id: Id, tag: &str) -> Result<(), TreeError<Id>> {
+        let Some(node) = self.nodes.get_mut(&id) else {
+            return Err(TreeError::NodeNotFound(id));
+        };
+        node.own_tags.remove(tag);
+        self.update_tags(&id);
+
+        self.forget_history();
+        self.record(TreeOp::RemoveTag {
+            id,
+            tag: tag.to_string(),
+        });
+        Ok(())
+    }
+
+    // Recompute the inherited tags of a node and its subtree from its parent. Uses an
+    // explicit stack, and each node's set is built once and moved into its last child.
+    fn update_tags(&mut self, node_id: &Id) {
+        let inherited = self
+            .parent_map
+            .get(node_id)
+            .and_then(|parent_id| self.nodes.get(parent_id))
+            .map(|parent| parent.effective_tags())
+            .unwrap_or_default();
+
+        let mut stack = vec![(node_id.clone(), inherited)];
+        while let Some((current_id, inherited)) = stack.pop() {
+            let Some(node) = self.nodes.get_mut(&current_id) else {
+                continue;
+            };
+            if node.children.is_empty() {
+                node.inherited_tags = inherited;
+                continue;
+            }
+
+            // What this node passes down: everything it inherited plus its own tags
+            let mut passed_down = inherited.clone();
+            passed_down.extend(node.own_tags.iter().cloned());
+            node.inherited_tags = inherited;
+
+            let mut children: Vec<Id> = node.children.iter().cloned().collect();
+            let last = children.pop().expect("node has children");
+            for child_id in children {
+                stack.push((child_id, passed_down.clone()));
+            }
+            stack.push((last, passed_down));
+        }
+    }
+
+    // List every node without a parent, in ascending order
+    pub fn get_roots(&self) -> Vec<Id>
+    where
+        Id: Ord,
+    {
+        let mut roots = self.root_ids();
+        roots.sort();
+        roots
+    }
+
+    // Every root, in no particular order
+    fn root_ids(&self) -> Vec<Id> {
+        self.nodes
+            .keys()
+            .filter(|id| !self.parent_map.contains_key(id))
+            .cloned()
+            .collect()
+    }
+
+    // `root` and everything below it, each node before its children but otherwise in no
+    // particular order
+    fn subtree_ids(&self, root: &Id) -> Vec<Id> {
+        let mut result = Vec::new();
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            if let Some(node) = self.nodes.get(id) {
+                result.push(id.clone());
+                stack.extend(node.children.iter());
+            }
+        }
+        result
+    }
+
+    // Every node in the forest, ordered so each child comes before its parent
+    fn children_first_order(&self) -> Vec<Id> {
+        let mut order = Vec::new();
+        let mut stack = self.root_ids();
+        while let Some(id) = stack.pop() {
+            if let Some(node) = self.nodes.get(&id) {
+                stack.extend(node.children.iter().cloned());
+            }
+            order.push(id);
+        }
+        order.reverse();
+        order
+    }
+
+    // Number of nodes in each node's subtree, counting the node itself
+    fn subtree_sizes(&self) -> HashMap<Id, usize> {
+        let mut sizes = HashMap::new();
+        for id in self.children_first_order() {
+            let size = 1 + self.nodes[&id]
+                .children
+                .iter()
+                .filter_map(|child_id| sizes.get(child_id))
+                .sum::<usize>();
+            sizes.insert(id, size);
+        }
+        sizes
+    }
+
+    pub fn print_tree(&self, root: Id, indent: usize) -> String
+    where
+        Id: fmt::Display,
+    {
+        let mut buffer = Vec::new();
+        self.write_tree(root, indent, &mut buffer)
+            .expect("writing to a Vec can't fail");
+        String::from_utf8(buffer).expect("tree output is valid UTF-8")
+    }
+
+    // Stream the same text as `print_tree` into `out`, one node at a time
+    pub fn write_tree<W: io::Write>(&self, root: Id, indent: usize, out: &mut W) -> io::Result<()>
+    where
+        Id: fmt::Display,
+    {
+        if let Some(node) = self.nodes.get(&root) {
+            write!(
+                out,
+                "{:indent$}- node {} ({:?})",
+                "",
+                node.id,
+                node.permission,
+                indent = indent
+            )?;
+            for child in &node.children {
+                self.write_tree(child.clone(), indent + 4, out)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Render the forest as a Graphviz digraph. Each node is labeled with its ID and
+    // permission, plus its effective tags on a second line; private nodes are filled gray.
+    // IDs are always quoted and IDs and tags are escaped, so any text yields a valid graph.
+    pub fn to_dot(&self) -> String
+    where
+        Id: Ord + fmt::Display,
+    {
+        let mut ids: Vec<&Id> = self.nodes.keys().collect();
+        ids.sort_unstable();
+
+        let mut result = String::from("digraph tree {\n");
+        for &id in &ids {
+            let node = &self.nodes[id];
+            let escaped_id = dot_escape(&id.to_string());
+            let mut label = format!("{} ({:?})", escaped_id, node.permission);
+            let mut tags: Vec<String> = node.effective_tags().into_iter().collect();
+            if !tags.is_empty() {
+                tags.sort();
+                label.push_str(&format!("\\n{}", dot_escape(&tags.join(", "))));
+            }
+            let style = match node.permission {
+                Permission::Private => ", style=filled, fillcolor=gray",
+                Permission::Protected => ", style=filled, fillcolor=lightgray",
+                Permission::Public => "",
+            };
+            result.push_str(&format!(
+                "    \"{}\" [label=\"{}\"{}];\n",
+                escaped_id, label, style
+            ));
+        }
+        for &id in &ids {
+            for child in self.get_children(id.clone()) {
+                result.push_str(&format!(
+                    "    \"{}\" -> \"{}\";\n",
+                    dot_escape(&id.to_string()),
+                    dot_escape(&child.to_string())
+                ));
+            }
+        }
+        result.push_str("}\n");
+        result
+    }
+
+    // Count how many levels up from `id` the nearest public node sits (0 if `id` is public itself)
+    pub fn steps_to_public(&self, id: Id) -> Option<usize> {
+        let mut current_id = &id;
+        let mut steps = 0;
+
+        loop {
+            let node = self.nodes.get(current_id)?;
+            if node.permission == Permission::Public {
+                return Some(steps);
+            }
+
+            // Reaching a root without finding a public node means there is none
+            current_id = self.parent_map.get(current_id)?;
+            steps += 1;
+        }
+    }
+
+    // Group every node into the connected tree it belongs to, ordered by root ID
+    pub fn components(&self) -> Vec<HashSet<Id>>
+    where
+        Id: Ord,
+    {
+        let mut components = Vec::new();
+        for root in self.get_roots() {
+            let mut component = HashSet::new();
+            let mut stack = vec![root];
+            while let Some(id) = stack.pop() {
+                if component.contains(&id) {
+                    continue;
+                }
+                if let Some(node) = self.nodes.get(&id) {
+                    stack.extend(node.children.iter().cloned());
+                }
+                component.insert(id);
+            }
+            components.push(component);
+        }
+        components
+    }
+
+    // Find nodes listed as a child by more than one node, which means the tree is corrupted
+    pub fn find_multi_parented(&self) -> Vec<Id>
+    where
+        Id: Ord,
+    {
+        let mut parent_counts: HashMap<&Id, usize> = HashMap::new();
+        for node in self.nodes.values() {
+            for child_id in &node.children {
+                *parent_counts.entry(child_id).or_insert(0) += 1;
+            }
+        }
+
+        let mut result: Vec<Id> = parent_counts
+            .into_iter()
+            .filter(|&(_, count)| count > 1)
+            .map(|(id, _)| id.clone())
+            .collect();
+        result.sort();
+        result
+    }
+
+    // Reattach every node below `root` breadth-first so no node has more than `fanout` children.
+    // Ancestry inside the subtree changes, so permissions are recomputed afterwards.
+    pub fn flatten_chain(&mut self, root: Id, fanout: usize) -> Result<(), TreeError<Id>>
+    where
+        Id: Ord,
+    {
+        if !self.nodes.contains_key(&root) {
+            return Err(TreeError::NodeNotFound(root));
+        }
+        if fanout == 0 {
+            return Err(TreeError::InvalidFanout(fanout));
+        }
+
+        // Collect the subtree in breadth-first order, visiting children by ascending ID
+        let order: Vec<Id> = self.bfs(root.clone()).collect();
+
+        // Detach everything inside the subtree, keeping `root` where it is
+        for id in &order {
+            if let Some(node) = self.nodes.get_mut(id) {
+                node.children.clear();
+            }
+            if *id != root {
+                self.parent_map.remove(id);
+            }
+        }
+
+        // Fill the subtree level by level, `fanout` children per node
+        for (index, id) in order.iter().enumerate().skip(1) {
+            let parent_id = &order[(index - 1) / fanout];
+            if let Some(parent_node) = self.nodes.get_mut(parent_id) {
+                parent_node.children.insert(id.clone());
+            }
+            self.parent_map.insert(id.clone(), parent_id.clone());
+        }
+
+        self.update_permission(&root);
+        self.update_tags(&root);
+        self.forget_history();
+
+        // Replicas get the same result by detaching every node and reattaching it in order
+        if self.op_log.is_some() {
+            for id in order.iter().skip(1) {
+                self.record(TreeOp::Detach(id.clone()));
+            }
+            for (index, id) in order.iter().enumerate().skip(1) {
+                self.record(TreeOp::Connect {
+                    parent: order[(index - 1) / fanout].clone(),
+                    child: id.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Collect every node within `radius` edges of `center`, walking both up and down
+    pub fn neighborhood(&self, center: Id, radius: usize) -> Vec<Id>
+    where
+        Id: Ord,
+    {
+        if !self.nodes.contains_key(&center) {
+            return Vec::new();
+        }
+
+        let mut visited = HashSet::from([center.clone()]);
+        let mut queue = VecDeque::from([(center, 0)]);
+        while let Some((id, distance)) = queue.pop_front() {
+            if distance == radius {
+                continue;
+            }
+
+            let mut neighbors: Vec<Id> = Vec::new();
+            if let Some(parent_id) = self.parent_map.get(&id) {
+                neighbors.push(parent_id.clone());
+            }
+            if let Some(node) = self.nodes.get(&id) {
+                neighbors.extend(node.children.iter().cloned());
+            }
+
+            for neighbor in neighbors {
+                if visited.insert(neighbor.clone()) {
+                    queue.push_back((neighbor, distance + 1));
+                }
+            }
+        }
+
+        let mut result: Vec<Id> = visited.into_iter().collect();
+        result.sort();
+        result
+    }
+
+    // Check that every node below `root` is reached exactly once and points back to its parent
+    pub fn is_valid_subtree(&self, root: Id) -> bool {
+        if !self.nodes.contains_key(&root) {
+            return false;
+        }
+
+        let mut visited = HashSet::from([&root]);
+        let mut stack = vec![&root];
+        while let Some(id) = stack.pop() {
+            let node = match self.nodes.get(id) {
+                Some(node) => node,
+                None => return false, // A child that doesn't exist
+            };
+
+            for child_id in &node.children {
+                // Reaching a node twice means a shared child or a cycle
+                if !visited.insert(child_id) {
+                    return false;
+                }
+                if self.parent_map.get(child_id) != Some(id) {
+                    return false;
+                }
+                stack.push(child_id);
+            }
+        }
+
+        true
+    }
+
+    // List the direct children of `id` that are private, in ascending order
+    pub fn private_children(&self, id: Id) -> Vec<Id>
+    where
+        Id: Ord,
+    {
+        let mut result: Vec<Id> = match self.nodes.get(&id) {
+            Some(node) => node
+                .children
+                .iter()
+                .filter(|child_id| {
+                    self.nodes
+                        .get(child_id)
+                        .is_some_and(|child| child.permission == Permission::Private)
+                })
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        result.sort();
+        result
+    }
+
+    // Hash every node's subtree from its shape, permissions and tags, ignoring IDs and child order
+    pub fn subtree_hashes(&self) -> HashMap<Id, u64> {
+        let mut hashes = HashMap::new();
+        for id in self.children_first_order() {
+            let node = &self.nodes[&id];
+
+            // Sort the child hashes so the result doesn't depend on HashSet order
+            let mut child_hashes: Vec<u64> = node
+                .children
+                .iter()
+                .filter_map(|child_id| hashes.get(child_id).copied())
+                .collect();
+            child_hashes.sort();
+
+            let mut tags: Vec<String> = node.effective_tags().into_iter().collect();
+            tags.sort();
+
+            let mut hasher = DefaultHasher::new();
+            node.permission.hash(&mut hasher);
+            tags.hash(&mut hasher);
+            child_hashes.hash(&mut hasher);
+            hashes.insert(id, hasher.finish());
+        }
+        hashes
+    }
+
+    // Declare the same permission on every root and recompute, returning how many roots changed
+    pub fn ensure_roots_permission(&mut self, permission: Permission) -> usize {
+        let mut changed = 0;
+        for root in self.root_ids() {
+            if let Some(node) = self.nodes.get_mut(&root) {
+                if node.declared_permission != permission {
+                    node.declared_permission = permission.clone();
+                    changed += 1;
+                    self.record(TreeOp::SetPermission {
+                        id: root.clone(),
+                        permission: permission.clone(),
+                    });
+                }
+            }
+            self.update_permission(&root);
+        }
+
+        if changed > 0 {
+            self.forget_history();
+        }
+        changed
+    }
+
+    // List every node without children, in ascending order
+    pub fn leaves(&self) -> Vec<Id>
+    where
+        Id: Ord,
+    {
+        let mut result: Vec<Id> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.children.is_empty())
+            .map(|(id, _)| id.clone())
+            .collect();
+        result.sort();
+        result
+    }
+
+    // List every private node without children, in ascending order
+    pub fn private_leaves(&self) -> Vec<Id>
+    where
+        Id: Ord,
+    {
+        self.leaves()
+            .into_iter()
+            .filter(|id| {
+                self.nodes
+                    .get(id)
+                    .is_some_and(|node| node.permission == Permission::Private)
+            })
+            .collect()
+    }
+
+    // Pair every node with its subtree size, largest first and ties by ascending ID
+    pub fn nodes_by_subtree_size(&self) -> Vec<(Id, usize)>
+    where
+        Id: Ord,
+    {
+        let mut result: Vec<(Id, usize)> = self.subtree_sizes().into_iter().collect();
+        result.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        result
+    }
+
+    // Merge every run of single-child nodes below `root` into its topmost node, returning how
+    // many nodes were removed. A merged node keeps the most restrictive permission of its run,
+    // the union of its tags and its own payload; the absorbed nodes' payloads are dropped.
+    pub fn collapse_single_child_chains(&mut self, root: Id) -> Result<usize, TreeError<Id>> {
+        if !self.nodes.contains_key(&root) {
+            return Err(TreeError::NodeNotFound(root));
+        }
+
+        let logging = self.op_log.is_some();
+        let mut ops = Vec::new();
+        let mut removed = 0;
+        let mut stack = vec![root.clone()];
+        while let Some(id) = stack.pop() {
+            // Keep absorbing the only child until this node branches or becomes a leaf
+            while self.nodes[&id].children.len() == 1 {
+                let child_id = self.nodes[&id].children.iter().next().unwrap().clone();
+                let child = self.nodes.remove(&child_id).unwrap();
+                self.parent_map.remove(&child_id);
+                for grandchild_id in &child.children {
+                    self.parent_map.insert(grandchild_id.clone(), id.clone());
+                }
+
+                let node = self.nodes.get_mut(&id).unwrap();
+                if logging {
+                    // The same absorption as single-node ops: lift the grandchildren out,
+                    // remove the now childless node and fold its permission and tags in
+                    let grandchildren: Vec<Id> = child.children.iter().cloned().collect();
+                    ops.extend(grandchildren.iter().cloned().map(TreeOp::Detach));
+                    ops.push(TreeOp::RemoveNode(child_id));
+                    if child.declared_permission > node.declared_permission {
+                        ops.push(TreeOp::SetPermission {
+                            id: id.clone(),
+                            permission: child.declared_permission.clone(),
+                        });
+                    }
+                    let mut tags: Vec<&String> =
+                        child.own_tags.difference(&node.own_tags).collect();
+                    tags.sort();
+                    ops.extend(tags.into_iter().map(|tag| TreeOp::AddTag {
+                        id: id.clone(),
+                        tag: tag.clone(),
+                    }));
+                    ops.extend(
+                        grandchildren
+                            .into_iter()
+                            .map(|grandchild_id| TreeOp::Connect {
+                                parent: id.clone(),
+                                child: grandchild_id,
+                            }),
+                    );
+                }
+                node.children = child.children;
+                node.own_tags.extend(child.own_tags);
+                node.declared_permission = node
+                    .declared_permission
+                    .clone()
+                    .max(child.declared_permission);
+                node.permission = node.permission.clone().max(child.permission);
+                removed += 1;
+            }
+
+            stack.extend(self.nodes[&id].children.iter().cloned());
+        }
+
+        self.forget_history();
+        for op in ops {
+            self.record(op);
+        }
+        Ok(removed)
+    }
+
+    // Report, in order, whether each ID is public; unknown IDs count as not accessible
+    pub fn access_mask(&self, ids: &[Id]) -> Vec<bool> {
+        ids.iter()
+            .map(|id| {
+                self.nodes
+                    .get(id)
+                    .is_some_and(|node| node.permission == Permission::Public)
+            })
+            .collect()
+    }
+
+    // Fraction of nodes that are public, or 0.0 for an empty tree
+    pub fn public_ratio(&self) -> f64 {
+        if self.nodes.is_empty() {
+            return 0.0;
+        }
+
+        let public_count = self
+            .nodes
+            .values()
+            .filter(|node| node.permission == Permission::Public)
+            .count();
+        public_count as f64 / self.nodes.len() as f64
+    }
+
+    // Check whether two forests have the same shape, ignoring IDs and permissions
+    pub fn is_isomorphic<U>(&self, other: &Tree<Id, U>) -> bool {
+        self.root_shapes() == other.root_shapes()
+    }
+
+    // Canonical shape string of every root's tree, sorted so root order doesn't matter
+    fn root_shapes(&self) -> Vec<String> {
+        let mut shapes: HashMap<Id, String> = HashMap::new();
+        for id in self.children_first_order() {
+            let mut child_shapes: Vec<&str> = self.nodes[&id]
+                .children
+                .iter()
+                .filter_map(|child_id| shapes.get(child_id).map(String::as_str))
+                .collect();
+            child_shapes.sort();
+            let shape = format!("({})", child_shapes.concat());
+            shapes.insert(id, shape);
+        }
+
+        let mut root_shapes: Vec<String> = self
+            .root_ids()
+            .iter()
+            .filter_map(|root| shapes.remove(root))
+            .collect();
+        root_shapes.sort();
+        root_shapes
+    }
+
+    // Export every edge in the forest, ordered by parent ID and then child ID
+    pub fn to_edge_records(&self) -> Vec<EdgeRecord<Id>>
+    where
+        Id: Ord,
+    {
+        let mut records: Vec<EdgeRecord<Id>> = self
+            .parent_map
+            .iter()
+            .filter_map(|(child, parent)| {
+                self.nodes.get(child).map(|node| EdgeRecord {
+                    parent: parent.clone(),
+                    child: child.clone(),
+                    permission: node.permission.clone(),
+                    tags: node.effective_tags(),
+                })
+            })
+            .collect();
+        records.sort_by(|a, b| (&a.parent, &a.child).cmp(&(&b.parent, &b.child)));
+        records
+    }
+
+    // Find the fewest nodes under `root` whose subtrees together contain exactly the `targets`.
+    // Returns `None` if `root` is missing or no exact cover exists, which happens when a
+    // target has a non-target descendant.
+    pub fn minimal_tag_cover(&self, root: Id, targets: &[Id]) -> Option<Vec<Id>>
+    where
+        Id: Ord,
+    {
+        if !self.nodes.contains_key(&root) {
+            return None;
+        }
+        let targets: HashSet<&Id> = targets.iter().collect();
+
+        // Order the subtree so every child comes before its parent
+        let mut order = Vec::new();
+        let mut stack = vec![&root];
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(self.nodes[id].children.iter());
+        }
+        order.reverse();
+
+        // A node is covered when it and its whole subtree are targets
+        let mut covered = HashSet::new();
+        for &id in &order {
+            let node = &self.nodes[id];
+            if targets.contains(id) {
+                if !node.children.iter().all(|child| covered.contains(child)) {
+                    return None;
+                }
+                covered.insert(id);
+            }
+        }
+
+        // Keep only the topmost covered nodes
+        let mut result: Vec<Id> = covered
+            .iter()
+            .filter(|&&id| {
+                *id == root
+                    || self
+                        .parent_map
+                        .get(id)
+                        .is_none_or(|parent_id| !covered.contains(parent_id))
+            })
+            .map(|&id| id.clone())
+            .collect();
+        result.sort();
+        Some(result)
+    }
+
+    // Run `f` on every node in the subtree rooted at `root`, then recompute inheritance once.
+    // The closure may change a node's declared permission, own tags and payload.
+    pub fn for_each_mut<F: FnMut(NodeMut<'_, Id, T>)>(
+        &mut self,
+        root: Id,
+        mut f: F,
+    ) -> Result<(), TreeError<Id>> {
+        if !self.nodes.contains_key(&root) {
+            return Err(TreeError::NodeNotFound(root));
+        }
+
+        // While logging, each node's changes are recorded as single-node ops. The payload
+        // can't be compared, so it is always recorded.
+        let clone_data = self.op_log.as_ref().map(|log| log.clone_data);
+        let mut ops = Vec::new();
+        let mut stack = vec![root.clone()];
+        while let Some(id) = stack.pop() {
+            if let Some(node) = self.nodes.get_mut(&id) {
+                let before = clone_data
+                    .is_some()
+                    .then(|| (node.declared_permission.clone(), node.own_tags.clone()));
+                f(NodeMut {
+                    id: &node.id,
+                    declared_permission: &mut node.declared_permission,
+                    own_tags: &mut node.own_tags,
+                    data: &mut node.data,
+                });
+                stack.extend(node.children.iter().cloned());
+
+                if let (Some(clone_data), Some((permission, tags))) = (clone_data, before) {
+                    if node.declared_permission != permission {
+                        ops.push(TreeOp::SetPermission {
+                            id: id.clone(),
+                            permission: node.declared_permission.clone(),
+                        });
+                    }
+                    let mut added: Vec<&String> = node.own_tags.difference(&tags).collect();
+                    added.sort();
+                    ops.extend(added.into_iter().map(|tag| TreeOp::AddTag {
+                        id: id.clone(),
+                        tag: tag.clone(),
+                    }));
+                    let mut dropped: Vec<&String> = tags.difference(&node.own_tags).collect();
+                    dropped.sort();
+                    ops.extend(dropped.into_iter().map(|tag| TreeOp::RemoveTag {
+                        id: id.clone(),
+                        tag: tag.clone(),
+                    }));
+                    ops.push(TreeOp::SetData {
+                        id,
+                        data: clone_data(&node.data),
+                    });
+                }
+            }
+        }
+
+        self.update_permission(&root);
+        self.update_tags(&root);
+        self.forget_history();
+        for op in ops {
+            self.record(op);
+        }
+        Ok(())
+    }
+
+    // Map every node to the IDs on its path from the root, usable as a hierarchy sort key
+    pub fn path_keys(&self) -> HashMap<Id, Vec<Id>> {
+        let mut keys: HashMap<Id, Vec<Id>> = HashMap::new();
+        let mut stack: Vec<(Id, Vec<Id>)> = self
+            .root_ids()
+            .into_iter()
+            .map(|root| (root, Vec::new()))
+            .collect();
+
+        while let Some((id, mut path)) = stack.pop() {
+            path.push(id.clone());
+            if let Some(node) = self.nodes.get(&id) {
+                for child_id in &node.children {
+                    stack.push((child_id.clone(), path.clone()));
+                }
+            }
+            keys.insert(id, path);
+        }
+        keys
+    }
+
+    // List the (parent, child) edges under `root` where the child's effective permission is
+    // stricter than its parent's, such as public to protected or protected to private.
+    // A root that is itself private has no incoming edge, so it yields no transition.
+    pub fn privacy_transitions(&self, root: Id) -> Vec<(Id, Id)>
+    where
+        Id: Ord,
+    {
+        let mut transitions = Vec::new();
+        let mut stack = vec![&root];
+        while let Some(id) = stack.pop() {
+            let node = match self.nodes.get(id) {
+                Some(node) => node,
+                None => continue,
+            };
+
+            for child_id in &node.children {
+                if let Some(child) = self.nodes.get(child_id) {
+                    if child.permission > node.permission {
+                        transitions.push((id.clone(), child_id.clone()));
+                    }
+                }
+                stack.push(child_id);
+            }
+        }
+        transitions.sort();
+        transitions
+    }
+
+    // Count public and private nodes per depth and list where each private region starts
+    pub fn access_report(&self) -> AccessReport<Id>
+    where
+        Id: Ord,
+    {
+        let mut report = AccessReport::default();
+        let mut stack: Vec<(&Id, usize)> = self
+            .nodes
+            .keys()
+            .filter(|id| !self.parent_map.contains_key(id))
+            .map(|root| (root, 0))
+            .collect();
+
+        while let Some((id, depth)) = stack.pop() {
+            let node = match self.nodes.get(id) {
+                Some(node) => node,
+                None => continue,
+            };
+
+            if report.levels.len() <= depth {
+                report.levels.resize(depth + 1, DepthAccess::default());
+            }
+            let level = &mut report.levels[depth];
+            match node.permission {
+                Permission::Public => level.public += 1,
+                Permission::Protected => level.protected += 1,
+                Permission::Private => {
+                    level.private += 1;
+                    let parent_is_private = self
+                        .parent_map
+                        .get(id)
+                        .and_then(|parent_id| self.nodes.get(parent_id))
+                        .is_some_and(|parent| parent.permission == Permission::Private);
+                    if !parent_is_private {
+                        level.private_region_roots.push(id.clone());
+                    }
+                }
+            }
+
+            for child_id in &node.children {
+                stack.push((child_id, depth + 1));
+            }
+        }
+
+        for level in &mut report.levels {
+            level.private_region_roots.sort();
+        }
+        report
+    }
+
+    // Declare the same permission on `root` and every descendant, returning how many nodes
+    // changed their effective permission. A stricter parent above `root` still wins.
+    pub fn set_subtree_permission(
+        &mut self,
+        root: Id,
+        permission: Permission,
+    ) -> Result<usize, TreeError<Id>> {
+        if !self.nodes.contains_key(&root) {
+            return Err(TreeError::NodeNotFound(root));
+        }
+
+        let mut before = HashMap::new();
+        let mut redeclared = Vec::new();
+        let mut stack = vec![root.clone()];
+        while let Some(id) = stack.pop() {
+            if let Some(node) = self.nodes.get_mut(&id) {
+                before.insert(id.clone(), node.permission.clone());
+                if node.declared_permission != permission {
+                    node.declared_permission = permission.clone();
+                    redeclared.push(id);
+                }
+                stack.extend(node.children.iter().cloned());
+            }
+        }
+        self.update_permission(&root);
+        self.forget_history();
+
+        for id in redeclared {
+            self.record(TreeOp::SetPermission {
+                id,
+                permission: permission.clone(),
+            });
+        }
+
+        let changed = before
+            .iter()
+            .filter(|(id, old)| {
+                self.nodes
+                    .get(id)
+                    .is_some_and(|node| node.permission != **old)
+            })
+            .count();
+
+        Ok(changed)
+    }
+
+    // List, in ascending order, the nodes that can only be reached through `gateway`.
+    // In a well-formed tree those are exactly its descendants; nodes that a corrupted
+    // `children` set lists below `gateway` but whose parent chain bypasses it are left out.
+    pub fn dominated_by(&self, gateway: Id) -> Vec<Id>
+    where
+        Id: Ord,
+    {
+        let mut result = Vec::new();
+        let mut visited = HashSet::from([&gateway]);
+        let mut stack = vec![&gateway];
+        while let Some(id) = stack.pop() {
+            if let Some(node) = self.nodes.get(id) {
+                for child_id in &node.children {
+                    if visited.insert(child_id)
+                        && self.is_descendant(gateway.clone(), child_id.clone())
+                    {
+                        result.push(child_id.clone());
+                        stack.push(child_id);
+                    }
+                }
+            }
+        }
+        result.sort();
+        result
+    }
+
+    // Number of nodes that can only be reached through `gateway`
+    pub fn count_dominated_by(&self, gateway: Id) -> usize
+    where
+        Id: Ord,
+    {
+        self.dominated_by(gateway).len()
+    }
+
+    // Map every node to the fraction of its subtree (itself included) that is private
+    pub fn privacy_skew(&self) -> HashMap<Id, f64> {
+        let sizes = self.subtree_sizes();
+        let mut private_counts: HashMap<Id, usize> = HashMap::new();
+        let mut skew = HashMap::new();
+
+        for id in self.children_first_order() {
+            let node = &self.nodes[&id];
+            let private_count = usize::from(node.permission == Permission::Private)
+                + node
+                    .children
+                    .iter()
+                    .filter_map(|child_id| private_counts.get(child_id))
+                    .sum::<usize>();
+            skew.insert(id.clone(), private_count as f64 / sizes[&id] as f64);
+            private_counts.insert(id, private_count);
+        }
+        skew
+    }
+
+    // Copy out the smallest subtree connecting `ids`: their common ancestor becomes the root and
+    // only nodes on the paths down to `ids` are kept. Permissions are copied as they are.
+    // Returns `None` if `ids` is empty, contains an unknown node, or spans separate trees.
+    pub fn spanning_subtree(&self, ids: &[Id]) -> Option<Tree<Id, T>>
+    where
+        T: Clone,
+    {
+        // The path from each node up to its root, starting with the node itself
+        let chain = |id: &Id| std::iter::once(id.clone()).chain(self.get_ancestors(id.clone()));
+
+        let (first, rest) = ids.split_first()?;
+        if ids.iter().any(|id| !self.nodes.contains_key(id)) {
+            return None;
+        }
+
+        let lca = rest.iter().try_fold(first.clone(), |lca, id| {
+            self.lowest_common_ancestor(lca, id.clone())
+        })?;
+
+        // Keep every node on the way from each target up to the common ancestor
+        let mut keep = HashSet::new();
+        for id in ids {
+            for ancestor in chain(id) {
+                if ancestor == lca || !keep.insert(ancestor) {
+                    break;
+                }
+            }
+        }
+        keep.insert(lca.clone());
+
+        let mut spanning = Tree::new();
+        for id in &keep {
+            let mut node = self.nodes[id].clone();
+            node.children.retain(|child_id| keep.contains(child_id));
+            spanning.nodes.insert(id.clone(), node);
+            if *id != lca {
+                spanning
+                    .parent_map
+                    .insert(id.clone(), self.parent_map[id].clone());
+            }
+        }
+
+        Some(spanning)
+    }
+
+    // Copy out only the nodes whose effective permission is public. Effective permissions
+    // only get stricter going down, so every copied node's ancestors are copied too and the
+    // public part of each tree keeps its shape.
+    pub fn public_view(&self) -> Tree<Id, T>
+    where
+        T: Clone,
+    {
+        let is_public = |id: &Id| {
+            self.nodes
+                .get(id)
+                .is_some_and(|node| node.permission == Permission::Public)
+        };
+
+        let mut view = Tree::new();
+        for (id, node) in &self.nodes {
+            if !is_public(id) {
+                continue;
+            }
+            let mut node = node.clone();
+            node.children.retain(|child_id| is_public(child_id));
+            view.nodes.insert(id.clone(), node);
+            if let Some(parent_id) = self.parent_map.get(id) {
+                view.parent_map.insert(id.clone(), parent_id.clone());
+            }
+        }
+        view
+    }
+
+    // Remove every private node, returning how many were removed. Everything below a
+    // private node is private too, so this drops whole subtrees starting wherever a
+    // private region begins.
+    pub fn prune_private(&mut self) -> usize {
+        let is_private = |tree: &Self, id: &Id| {
+            tree.nodes
+                .get(id)
+                .is_some_and(|node| node.permission == Permission::Private)
+        };
+
+        let region_roots: Vec<Id> = self
+            .nodes
+            .keys()
+            .filter(|id| {
+                is_private(self, id)
+                    && !self
+                        .parent_map
+                        .get(id)
+                        .is_some_and(|parent_id| is_private(self, parent_id))
+            })
+            .cloned()
+            .collect();
+
+        region_roots
+            .into_iter()
+            .filter_map(|root| self.remove_subtree(root).ok())
+            .sum()
+    }
+
+    // Import every node and edge of `other`. With `attach_under`, each of `other`'s roots
+    // becomes a child of that node and inherits from it; otherwise they stay separate roots.
+    // Nothing changes if an ID exists in both trees or `attach_under` is missing.
+    pub fn merge(
+        &mut self,
+        other: Tree<Id, T>,
+        attach_under: Option<Id>,
+    ) -> Result<(), TreeError<Id>> {
+        if let Some(collision) = other.nodes.keys().find(|id| self.nodes.contains_key(id)) {
+            return Err(TreeError::NodeAlreadyExists(collision.clone()));
+        }
+        if let Some(parent_id) = &attach_under {
+            if !self.nodes.contains_key(parent_id) {
+                return Err(TreeError::NodeNotFound(parent_id.clone()));
+            }
+        }
+
+        let roots = other.root_ids();
+        let mut ops = match self.op_log.as_ref() {
+            Some(log) => other.rebuild_ops(log.clone_data),
+            None => Vec::new(),
+        };
+        self.nodes.extend(other.nodes);
+        self.parent_map.extend(other.parent_map);
+
+        if let Some(parent_id) = attach_under {
+            for root in &roots {
+                if let Some(parent_node) = self.nodes.get_mut(&parent_id) {
+                    parent_node.children.insert(root.clone());
+                }
+                self.parent_map.insert(root.clone(), parent_id.clone());
+                self.update_permission(root);
+                self.update_tags(root);
+                ops.push(TreeOp::Connect {
+                    parent: parent_id.clone(),
+                    child: root.clone(),
+                });
+            }
+        }
+
+        self.forget_history();
+        for op in ops {
+            self.record(op);
+        }
+        Ok(())
+    }
+
+    // Copy the subtree under `root` into a new tree where `root` has no parent. Inheritance
+    // is recomputed from `root` down, so permissions and tags that only came from above
+    // `root` are dropped. Returns `None` if `root` doesn't exist.
+    pub fn extract_subtree(&self, root: Id) -> Option<Tree<Id, T>>
+    where
+        T: Clone,
+    {
+        if !self.nodes.contains_key(&root) {
+            return None;
+        }
+
+        let mut extracted = Tree::new();
+        for id in self.subtree_ids(&root) {
+            if id != root {
+                extracted
+                    .parent_map
+                    .insert(id.clone(), self.parent_map[&id].clone());
+            }
+            extracted.nodes.insert(id.clone(), self.nodes[&id].clone());
+        }
+
+        extracted.update_permission(&root);
+        extracted.update_tags(&root);
+        Some(extracted)
+    }
+
+    // Find every node whose effective tags include `tag`, in ascending order
+    pub fn find_nodes_by_tag(&self, tag: &str) -> Vec<Id>
+    where
+        Id: Ord,
+    {
+        let mut result: Vec<Id> = self
+            .nodes
+            .values()
+            .filter(|node| node.own_tags.contains(tag) || node.inherited_tags.contains(tag))
+            .map(|node| node.id.clone())
+            .collect();
+        result.sort();
+        result
+    }
+
+    // List the nodes that would become roots if `id` were removed and its children orphaned
+    pub fn new_roots_if_removed(&self, id: Id) -> Vec<Id>
+    where
+        Id: Ord,
+    {
+        self.get_children(id)
+    }
+}
+
+// Escape backslashes and double quotes so `text` can sit inside a quoted DOT string
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(feature = "serde")]
+impl<Id, T> Tree<Id, T>
+where
+    Id: NodeId + serde::Serialize + serde::de::DeserializeOwned,
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    // Serialize the tree to a JSON string
+    pub fn to_json(&self) -> Result<String, TreeError<Id>> {
+        serde_json::to_string(self).map_err(|e| TreeError::Json(e.to_string()))
+    }
+
+    // Load a tree from JSON written by `to_json`, rejecting structurally broken data.
+    // Effective permissions and inherited tags are recomputed from the declared
+    // values, so hand-edited derived fields can't loosen access.
+    pub fn from_json(s: &str) -> Result<Tree<Id, T>, TreeError<Id>> {
+        let mut tree: Tree<Id, T> =
+            serde_json::from_str(s).map_err(|e| TreeError::Json(e.to_string()))?;
+        tree.validate()?;
+        for root in tree.root_ids() {
+            tree.update_permission(&root);
+            tree.update_tags(&root);
+        }
+        Ok(tree)
     }
 }
@@ -1,64 +1,83 @@
-use permission_tree::{Permission, Tree};
+use permission_tree::{
+    DepthAccess, EdgeRecord, Permission, ReconnectPolicy, Tree, TreeError, TreeNode, TreeOp,
+};
+use std::collections::HashSet;
 
 #[test]
 fn test_add_node() {
-    let mut tree = Tree::new();
-    tree.add_node(1, Permission::Public);
+    let mut tree = Tree::<u32>::new();
+    assert_eq!(tree.add_node(1, Permission::Public, ()), Ok(()));
 
     // Assert that node with ID 1 has been added
     assert!(tree.nodes.contains_key(&1));
     assert_eq!(tree.nodes.get(&1).unwrap().permission, Permission::Public);
+
+    // Adding the same ID again is an error and keeps the original node
+    assert_eq!(
+        tree.add_node(1, Permission::Private, ()),
+        Err(TreeError::NodeAlreadyExists(1))
+    );
+    assert_eq!(tree.nodes.get(&1).unwrap().permission, Permission::Public);
 }
 
 #[test]
 fn test_connect_nodes() {
-    let mut tree = Tree::new();
+    let mut tree = Tree::<u32>::new();
 
     // Adding nodes
-    tree.add_node(1, Permission::Public); // root node
-    tree.add_node(2, Permission::Private);
-    tree.add_node(3, Permission::Public);
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
 
     // Connecting nodes
-    tree.connect_nodes(1, 2);
-    tree.connect_nodes(1, 3);
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
 
     // Check if the connections are established correctly
     assert!(tree.nodes.get(&1).unwrap().children.contains(&2));
     assert!(tree.nodes.get(&1).unwrap().children.contains(&3));
 
     // Attempt to connect a node to itself (should fail)
-    tree.connect_nodes(1, 1);
-    assert_eq!(tree.nodes.get(&1).unwrap().children.contains(&1), false);
+    assert_eq!(tree.connect_nodes(1, 1), Err(TreeError::SelfParent(1)));
+    assert!(!tree.nodes.get(&1).unwrap().children.contains(&1));
 
     // Attempt to connect node 2 to node 3, which should fail
-    tree.connect_nodes(2, 3);
-    assert_eq!(tree.nodes.get(&2).unwrap().children.contains(&3), false);
+    assert_eq!(
+        tree.connect_nodes(2, 3),
+        Err(TreeError::ChildAlreadyHasParent {
+            child: 3,
+            existing_parent: 1,
+        })
+    );
+    assert!(!tree.nodes.get(&2).unwrap().children.contains(&3));
+
+    // Connecting to a node that doesn't exist is an error
+    assert_eq!(tree.connect_nodes(1, 99), Err(TreeError::NodeNotFound(99)));
     assert_eq!(tree.parent_map.get(&3), Some(&1));
 }
 
 #[test]
 fn test_permission_inheritance() {
-    let mut tree = Tree::new();
+    let mut tree = Tree::<u32>::new();
 
     // Adding nodes
-    tree.add_node(1, Permission::Public); // root node
-    tree.add_node(2, Permission::Public);
-    tree.add_node(3, Permission::Private); // private node
-    tree.add_node(4, Permission::Public);
-    tree.add_node(5, Permission::Public);
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap(); // private node
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
 
     // Connecting nodes
-    tree.connect_nodes(1, 3);
-    tree.connect_nodes(2, 4);
-    tree.connect_nodes(2, 5);
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(2, 5).unwrap();
 
     // Before the connection, node 4 and node 5 should be public
     assert_eq!(tree.nodes.get(&4).unwrap().permission, Permission::Public);
     assert_eq!(tree.nodes.get(&5).unwrap().permission, Permission::Public);
 
     // Connecting node 2 (public) as a child of node 3 (private)
-    tree.connect_nodes(3, 2);
+    tree.connect_nodes(3, 2).unwrap();
 
     // After connection, node 2 and all its descendants (4, 5) should become private
     assert_eq!(tree.nodes.get(&2).unwrap().permission, Permission::Private);
@@ -68,18 +87,18 @@ fn test_permission_inheritance() {
 
 #[test]
 fn test_is_descendant() {
-    let mut tree = Tree::new();
+    let mut tree = Tree::<u32>::new();
 
     // Adding nodes
-    tree.add_node(1, Permission::Public); // root node
-    tree.add_node(2, Permission::Public);
-    tree.add_node(3, Permission::Public);
-    tree.add_node(4, Permission::Public);
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
 
     // Connecting nodes
-    tree.connect_nodes(1, 2);
-    tree.connect_nodes(2, 3);
-    tree.connect_nodes(3, 4);
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(3, 4).unwrap();
 
     // Test if node 4 is a descendant of node 1
     assert!(tree.is_descendant(1, 4));
@@ -92,23 +111,23 @@ fn test_is_descendant() {
 
 #[test]
 fn test_move_subtree() {
-    let mut tree = Tree::new();
+    let mut tree = Tree::<u32>::new();
 
     // Adding nodes
-    tree.add_node(1, Permission::Public); // root node
-    tree.add_node(2, Permission::Public);
-    tree.add_node(3, Permission::Private); // private node
-    tree.add_node(4, Permission::Public);
-    tree.add_node(5, Permission::Public);
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap(); // private node
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
 
     // Connecting nodes
-    tree.connect_nodes(1, 2);
-    tree.connect_nodes(1, 3);
-    tree.connect_nodes(2, 4);
-    tree.connect_nodes(2, 5);
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(2, 5).unwrap();
 
     // Move subtree rooted at node 2 under node 3 (private)
-    tree.move_subtree(2, 3);
+    tree.move_subtree(2, 3).unwrap();
 
     // After moving, nodes 2, 4, and 5 should inherit private permission from node 3
     assert_eq!(tree.nodes.get(&2).unwrap().permission, Permission::Private);
@@ -118,23 +137,2546 @@ fn test_move_subtree() {
 
 #[test]
 fn test_move_subtree_invalid() {
-    let mut tree = Tree::new();
+    let mut tree = Tree::<u32>::new();
 
     // Adding nodes
-    tree.add_node(1, Permission::Public); // root node
-    tree.add_node(2, Permission::Public);
-    tree.add_node(3, Permission::Private); // private node
-    tree.add_node(4, Permission::Public);
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap(); // private node
+    tree.add_node(4, Permission::Public, ()).unwrap();
 
     // Connecting nodes
-    tree.connect_nodes(1, 2);
-    tree.connect_nodes(2, 3);
-    tree.connect_nodes(3, 4);
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(3, 4).unwrap();
 
     // Try to move node 3 under node 4, which is its descendant (should fail)
-    tree.move_subtree(3, 4);
+    assert_eq!(
+        tree.move_subtree(3, 4),
+        Err(TreeError::WouldCreateCycle { node: 3, target: 4 })
+    );
 
     // After moving, nodes 2, 4, and 5 should inherit private permission from node 3
     assert!(tree.nodes.get(&2).unwrap().children.contains(&3));
     assert!(!tree.nodes.get(&4).unwrap().children.contains(&3));
 }
+
+#[test]
+fn test_steps_to_public() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap(); // private node
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Private, ()).unwrap(); // private root
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+
+    // Node 3 is private by inheritance and sits two levels below public node 1
+    assert_eq!(tree.steps_to_public(3), Some(2));
+    assert_eq!(tree.steps_to_public(1), Some(0));
+
+    // A private root has no public ancestor, and unknown nodes have no answer
+    assert_eq!(tree.steps_to_public(4), None);
+    assert_eq!(tree.steps_to_public(99), None);
+}
+
+#[test]
+fn test_components() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes for two separate trees
+    tree.add_node(1, Permission::Public, ()).unwrap(); // first root
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap(); // second root
+    tree.add_node(5, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(4, 5).unwrap();
+
+    let components = tree.components();
+
+    // Every node lands in exactly one component
+    assert_eq!(components.len(), 2);
+    assert_eq!(components[0], HashSet::from([1, 2, 3]));
+    assert_eq!(components[1], HashSet::from([4, 5]));
+}
+
+#[test]
+fn test_connect_root_with_descendants() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes for two separate trees
+    tree.add_node(1, Permission::Private, ()).unwrap(); // private root
+    tree.add_node(2, Permission::Public, ()).unwrap(); // root of a second tree
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+
+    // Build the second tree before attaching it
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(3, 4).unwrap();
+    assert_eq!(tree.nodes.get(&4).unwrap().permission, Permission::Public);
+
+    // Attach the whole second tree under the private root
+    tree.connect_nodes(1, 2).unwrap();
+
+    // The subtree stays intact and inherits the private permission all the way down
+    assert_eq!(tree.parent_map.get(&2), Some(&1));
+    assert_eq!(tree.parent_map.get(&4), Some(&3));
+    assert_eq!(tree.nodes.get(&2).unwrap().permission, Permission::Private);
+    assert_eq!(tree.nodes.get(&3).unwrap().permission, Permission::Private);
+    assert_eq!(tree.nodes.get(&4).unwrap().permission, Permission::Private);
+}
+
+#[test]
+fn test_find_multi_parented() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+
+    // A tree built through the API never has shared children
+    assert!(tree.find_multi_parented().is_empty());
+
+    // Corrupt the tree by listing node 3 under node 2 as well
+    tree.nodes.get_mut(&2).unwrap().children.insert(3);
+    assert_eq!(tree.find_multi_parented(), vec![3]);
+}
+
+#[test]
+fn test_flatten_chain() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes and connecting them into a 7-node chain
+    for id in 1..=7 {
+        tree.add_node(id, Permission::Public, ()).unwrap();
+    }
+    for id in 1..7 {
+        tree.connect_nodes(id, id + 1).unwrap();
+    }
+
+    // Count the parent links above a node
+    let depth = |tree: &Tree, mut id: u32| {
+        let mut depth = 0;
+        while let Some(&parent_id) = tree.parent_map.get(&id) {
+            id = parent_id;
+            depth += 1;
+        }
+        depth
+    };
+    assert_eq!(depth(&tree, 7), 6);
+
+    assert_eq!(tree.flatten_chain(1, 2), Ok(()));
+
+    // Every node has at most two children and the deepest node is now two levels down
+    assert!(tree.nodes.values().all(|node| node.children.len() <= 2));
+    let max_depth = (1..=7).map(|id| depth(&tree, id)).max().unwrap();
+    assert_eq!(max_depth, 2);
+
+    // Every node is still part of the tree rooted at node 1
+    for id in 2..=7 {
+        assert!(tree.is_descendant(1, id));
+    }
+
+    // Invalid input is rejected
+    assert_eq!(tree.flatten_chain(99, 2), Err(TreeError::NodeNotFound(99)));
+    assert_eq!(tree.flatten_chain(1, 0), Err(TreeError::InvalidFanout(0)));
+}
+
+#[test]
+fn test_neighborhood() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Private, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+    tree.add_node(6, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(2, 5).unwrap();
+    tree.connect_nodes(4, 6).unwrap();
+
+    // Radius 1 covers the node, its parent and its direct children
+    assert_eq!(tree.neighborhood(2, 1), vec![1, 2, 4, 5]);
+
+    // Radius 2 also reaches the sibling through the parent and the grandchild
+    assert_eq!(tree.neighborhood(2, 2), vec![1, 2, 3, 4, 5, 6]);
+
+    // Radius 0 is just the node itself, and unknown nodes have no neighborhood
+    assert_eq!(tree.neighborhood(2, 0), vec![2]);
+    assert!(tree.neighborhood(99, 1).is_empty());
+}
+
+#[test]
+fn test_is_valid_subtree() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+
+    // A tree built through the API is well-formed
+    assert!(tree.is_valid_subtree(1));
+    assert!(tree.is_valid_subtree(2));
+    assert!(!tree.is_valid_subtree(99));
+
+    // Corrupt the tree by sharing node 4 between nodes 2 and 3
+    tree.nodes.get_mut(&3).unwrap().children.insert(4);
+    assert!(!tree.is_valid_subtree(1));
+    assert!(!tree.is_valid_subtree(3));
+
+    // The untouched branch is still valid on its own
+    assert!(tree.is_valid_subtree(2));
+}
+
+#[test]
+fn test_private_children() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Private, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(1, 4).unwrap();
+    tree.connect_nodes(2, 5).unwrap();
+
+    // Only direct private children are listed, not private grandchildren
+    assert_eq!(tree.private_children(1), vec![2, 4]);
+    assert_eq!(tree.private_children(2), vec![5]);
+    assert!(tree.private_children(3).is_empty());
+    assert!(tree.private_children(99).is_empty());
+}
+
+#[test]
+fn test_subtree_hashes() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes for a root with two identical branches
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Private, ()).unwrap();
+    tree.add_node(5, Permission::Private, ()).unwrap();
+    tree.add_node(6, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(3, 5).unwrap();
+
+    let before = tree.subtree_hashes();
+    assert_eq!(before.len(), 6);
+
+    // Structurally identical branches hash the same despite different IDs
+    assert_eq!(before[&2], before[&3]);
+    assert_eq!(before[&4], before[&5]);
+
+    // Growing one branch changes only that branch and its ancestors
+    tree.connect_nodes(4, 6).unwrap();
+    let after = tree.subtree_hashes();
+    assert_ne!(after[&4], before[&4]);
+    assert_ne!(after[&2], before[&2]);
+    assert_ne!(after[&1], before[&1]);
+    assert_eq!(after[&3], before[&3]);
+    assert_eq!(after[&5], before[&5]);
+}
+
+#[test]
+fn test_ensure_roots_permission() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes for three separate trees
+    tree.add_node(1, Permission::Public, ()).unwrap(); // public root
+    tree.add_node(2, Permission::Private, ()).unwrap(); // private root
+    tree.add_node(3, Permission::Private, ()).unwrap(); // private root
+    tree.add_node(4, Permission::Private, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 4).unwrap();
+    tree.connect_nodes(1, 5).unwrap();
+
+    // Two of the three roots need to change
+    assert_eq!(tree.ensure_roots_permission(Permission::Public), 2);
+    for root in [1, 2, 3] {
+        assert_eq!(
+            tree.nodes.get(&root).unwrap().permission,
+            Permission::Public
+        );
+    }
+
+    // Children keep their own permission under a public root
+    assert_eq!(tree.nodes.get(&4).unwrap().permission, Permission::Private);
+
+    // Making every root private pushes the permission down as well
+    assert_eq!(tree.ensure_roots_permission(Permission::Private), 3);
+    assert_eq!(tree.nodes.get(&5).unwrap().permission, Permission::Private);
+
+    // Nothing changes when the roots already match, so undo history survives
+    tree.add_node(6, Permission::Private, ()).unwrap(); // private root
+    assert_eq!(tree.ensure_roots_permission(Permission::Private), 0);
+    assert!(tree.undo());
+    assert!(!tree.nodes.contains_key(&6));
+}
+
+#[test]
+fn test_private_leaves() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Private, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(3, 5).unwrap();
+
+    // Node 4 is private by inheritance and node 5 by declaration; node 2 is not a leaf
+    assert_eq!(tree.private_leaves(), vec![4, 5]);
+}
+
+#[test]
+fn test_reconnect_policy_reparent() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap(); // private node
+    tree.add_node(4, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+
+    // By default connecting an already-parented child is an error
+    assert_eq!(
+        tree.connect_nodes(3, 2),
+        Err(TreeError::ChildAlreadyHasParent {
+            child: 2,
+            existing_parent: 1,
+        })
+    );
+    assert_eq!(tree.parent_map.get(&2), Some(&1));
+    tree.set_reconnect_policy(ReconnectPolicy::Error);
+    assert!(tree.connect_nodes(3, 2).is_err());
+
+    // With the reject policy the request succeeds but nothing moves
+    tree.set_reconnect_policy(ReconnectPolicy::Reject);
+    assert_eq!(tree.connect_nodes(3, 2), Ok(()));
+    assert_eq!(tree.parent_map.get(&2), Some(&1));
+    assert!(!tree.nodes.get(&3).unwrap().children.contains(&2));
+
+    // With the reparent policy the subtree moves under the new parent
+    tree.set_reconnect_policy(ReconnectPolicy::Reparent);
+    tree.connect_nodes(3, 2).unwrap();
+    assert_eq!(tree.parent_map.get(&2), Some(&3));
+    assert!(!tree.nodes.get(&1).unwrap().children.contains(&2));
+    assert!(tree.nodes.get(&3).unwrap().children.contains(&2));
+
+    // Inheritance is recomputed for the moved subtree
+    assert_eq!(tree.nodes.get(&2).unwrap().permission, Permission::Private);
+    assert_eq!(tree.nodes.get(&4).unwrap().permission, Permission::Private);
+}
+
+#[test]
+fn test_nodes_by_subtree_size() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(2, 5).unwrap();
+
+    // The root comes first with the total count, leaves last in ID order
+    assert_eq!(
+        tree.nodes_by_subtree_size(),
+        vec![(1, 5), (2, 3), (3, 1), (4, 1), (5, 1)]
+    );
+}
+
+#[test]
+fn test_collapse_single_child_chains() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Private, ()).unwrap(); // private end of the chain
+    tree.add_node(5, Permission::Public, ()).unwrap();
+    tree.add_node(6, Permission::Public, ()).unwrap();
+    tree.add_node(7, Permission::Public, ()).unwrap();
+
+    // Node 1 branches into a chain 2 -> 3 -> 4 and a chain 5 -> 6 -> 7
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(3, 4).unwrap();
+    tree.connect_nodes(1, 5).unwrap();
+    tree.connect_nodes(5, 6).unwrap();
+    tree.connect_nodes(6, 7).unwrap();
+    tree.add_tag_to_node(2, "a").unwrap();
+    tree.add_tag_to_node(3, "b").unwrap();
+    tree.add_tag_to_node(4, "c").unwrap();
+
+    assert_eq!(tree.collapse_single_child_chains(1), Ok(4));
+    assert_eq!(
+        tree.collapse_single_child_chains(99),
+        Err(TreeError::NodeNotFound(99))
+    );
+
+    // Each chain collapsed into its topmost node
+    assert_eq!(tree.nodes.len(), 3);
+    assert_eq!(tree.nodes.get(&1).unwrap().children, HashSet::from([2, 5]));
+    assert!(tree.nodes.get(&2).unwrap().children.is_empty());
+    assert!(tree.nodes.get(&5).unwrap().children.is_empty());
+    assert!(!tree.parent_map.contains_key(&4));
+
+    // The merged node keeps the most restrictive permission and all tags of its chain
+    assert_eq!(tree.nodes.get(&2).unwrap().permission, Permission::Private);
+    assert_eq!(tree.nodes.get(&5).unwrap().permission, Permission::Public);
+    assert_eq!(
+        tree.nodes.get(&2).unwrap().own_tags,
+        HashSet::from(["a".to_string(), "b".to_string(), "c".to_string()])
+    );
+}
+
+#[test]
+fn test_access_mask() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(1, 4).unwrap();
+
+    // Results line up with the input, including repeats and unknown IDs
+    assert_eq!(
+        tree.access_mask(&[1, 2, 3, 4, 99, 1]),
+        vec![true, false, false, true, false, true]
+    );
+    assert!(tree.access_mask(&[]).is_empty());
+}
+
+#[test]
+fn test_public_ratio() {
+    let mut tree = Tree::<u32>::new();
+    assert_eq!(tree.public_ratio(), 0.0);
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+
+    // Connecting nodes; node 3 becomes private under node 2
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(1, 4).unwrap();
+
+    assert_eq!(tree.public_ratio(), 0.5);
+}
+
+#[test]
+fn test_is_isomorphic() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes: a root with one leaf and one branch of two leaves
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(3, 4).unwrap();
+    tree.connect_nodes(3, 5).unwrap();
+
+    // Same shape with different IDs and permissions
+    let mut same = Tree::<u32>::new();
+    same.add_node(10, Permission::Private, ()).unwrap(); // root node
+    same.add_node(20, Permission::Public, ()).unwrap();
+    same.add_node(30, Permission::Private, ()).unwrap();
+    same.add_node(40, Permission::Public, ()).unwrap();
+    same.add_node(50, Permission::Public, ()).unwrap();
+    same.connect_nodes(10, 20).unwrap();
+    same.connect_nodes(20, 30).unwrap();
+    same.connect_nodes(20, 40).unwrap();
+    same.connect_nodes(10, 50).unwrap();
+    assert!(tree.is_isomorphic(&same));
+
+    // A chain of five nodes has a different shape
+    let mut chain = Tree::<u32>::new();
+    for id in 1..=5 {
+        chain.add_node(id, Permission::Public, ()).unwrap();
+    }
+    for id in 1..5 {
+        chain.connect_nodes(id, id + 1).unwrap();
+    }
+    assert!(!tree.is_isomorphic(&chain));
+
+    // An extra standalone root makes the forests differ
+    same.add_node(60, Permission::Public, ()).unwrap();
+    assert!(!tree.is_isomorphic(&same));
+}
+
+#[test]
+fn test_to_edge_records() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes for two separate trees
+    tree.add_node(1, Permission::Public, ()).unwrap(); // first root
+    tree.add_node(2, Permission::Private, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap(); // second root
+    tree.add_node(5, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(4, 5).unwrap();
+    tree.add_tag_to_node(1, "docs").unwrap();
+    tree.add_tag_to_node(3, "draft").unwrap();
+
+    let records = tree.to_edge_records();
+
+    // Every edge across the forest is exported with the child's permission and tags
+    assert_eq!(records.len(), 3);
+    assert_eq!(
+        records[1],
+        EdgeRecord {
+            parent: 2,
+            child: 3,
+            permission: Permission::Private,
+            tags: HashSet::from(["docs".to_string(), "draft".to_string()]),
+        }
+    );
+    assert_eq!((records[2].parent, records[2].child), (4, 5));
+}
+
+#[test]
+fn test_minimal_tag_cover() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+    tree.add_node(6, Permission::Public, ()).unwrap();
+    tree.add_node(7, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(2, 5).unwrap();
+    tree.connect_nodes(3, 6).unwrap();
+    tree.connect_nodes(3, 7).unwrap();
+
+    // Node 2's whole subtree collapses to node 2, while node 6 is covered alone
+    assert_eq!(tree.minimal_tag_cover(1, &[2, 4, 5, 6]), Some(vec![2, 6]));
+
+    // Covering everything only needs the root
+    assert_eq!(
+        tree.minimal_tag_cover(1, &[1, 2, 3, 4, 5, 6, 7]),
+        Some(vec![1])
+    );
+
+    // Node 3 can't be tagged without also reaching node 7, so there is no exact cover
+    assert_eq!(tree.minimal_tag_cover(1, &[3, 6]), None);
+
+    // Targets outside the subtree are ignored
+    assert_eq!(tree.minimal_tag_cover(2, &[4, 6]), Some(vec![4]));
+    assert_eq!(tree.minimal_tag_cover(99, &[4]), None);
+}
+
+#[test]
+fn test_for_each_mut() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 5).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(3, 4).unwrap();
+
+    // Collect visited IDs and make only node 3 private
+    let mut visited = Vec::new();
+    tree.for_each_mut(2, |node| {
+        visited.push(*node.id);
+        if *node.id == 3 {
+            *node.declared_permission = Permission::Private;
+        }
+    })
+    .unwrap();
+    visited.sort();
+    assert_eq!(visited, vec![2, 3, 4]);
+
+    // Node 4 inherits the new private permission; nodes outside the subtree are untouched
+    assert_eq!(tree.nodes.get(&2).unwrap().permission, Permission::Public);
+    assert_eq!(tree.nodes.get(&4).unwrap().permission, Permission::Private);
+    assert_eq!(tree.nodes.get(&5).unwrap().permission, Permission::Public);
+
+    // Tags declared through the view reach the rest of the subtree
+    tree.for_each_mut(2, |node| {
+        if *node.id == 2 {
+            node.own_tags.insert("bulk".to_string());
+        }
+    })
+    .unwrap();
+    assert!(tree.nodes[&4].inherited_tags.contains("bulk"));
+
+    // Making every node under the root private leaves the whole tree private
+    tree.for_each_mut(1, |node| *node.declared_permission = Permission::Private)
+        .unwrap();
+    assert!(tree
+        .nodes
+        .values()
+        .all(|node| node.permission == Permission::Private));
+
+    // An unknown root is rejected and leaves the undo history alone
+    tree.add_node(6, Permission::Public, ()).unwrap();
+    assert_eq!(
+        tree.for_each_mut(99, |_| {}),
+        Err(TreeError::NodeNotFound(99))
+    );
+    assert!(tree.undo());
+}
+
+#[test]
+fn test_path_keys() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Private, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(3, 4).unwrap();
+    tree.connect_nodes(2, 5).unwrap();
+
+    let keys = tree.path_keys();
+    assert_eq!(keys[&4], vec![1, 3, 4]);
+    assert_eq!(keys[&1], vec![1]);
+
+    // Sorting by path key gives a pre-order with siblings in ID order
+    let mut ids: Vec<u32> = tree.nodes.keys().copied().collect();
+    ids.sort_by_key(|id| keys[id].clone());
+    assert_eq!(ids, vec![1, 2, 5, 3, 4]);
+}
+
+#[test]
+fn test_privacy_transitions() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Private, ()).unwrap();
+    tree.add_node(6, Permission::Public, ()).unwrap();
+    tree.add_node(7, Permission::Protected, ()).unwrap();
+    tree.add_node(8, Permission::Private, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(1, 4).unwrap();
+    tree.connect_nodes(4, 5).unwrap();
+    tree.connect_nodes(5, 6).unwrap();
+    tree.connect_nodes(1, 7).unwrap();
+    tree.connect_nodes(7, 8).unwrap();
+
+    // Only the edges where access tightens are locking edges
+    assert_eq!(
+        tree.privacy_transitions(1),
+        vec![(1, 2), (1, 7), (4, 5), (7, 8)]
+    );
+    assert_eq!(tree.privacy_transitions(4), vec![(4, 5)]);
+    assert_eq!(tree.privacy_transitions(7), vec![(7, 8)]);
+    assert!(tree.privacy_transitions(2).is_empty());
+
+    // A private root has no edge leading into it
+    tree.add_node(9, Permission::Private, ()).unwrap(); // private root
+    tree.add_node(10, Permission::Public, ()).unwrap();
+    tree.connect_nodes(9, 10).unwrap();
+    assert!(tree.privacy_transitions(9).is_empty());
+}
+
+#[test]
+fn test_can_move() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+
+    // Moving node 2 under node 3 is allowed
+    assert_eq!(tree.can_move(2, 3), Ok(()));
+
+    // Moving node 2 under its own descendant is not
+    assert_eq!(
+        tree.can_move(2, 4),
+        Err(TreeError::WouldCreateCycle { node: 2, target: 4 })
+    );
+    assert_eq!(tree.can_move(2, 2), Err(TreeError::SelfParent(2)));
+    assert_eq!(tree.can_move(2, 99), Err(TreeError::NodeNotFound(99)));
+
+    // Checking never changes the tree
+    assert_eq!(tree.parent_map.get(&2), Some(&1));
+
+    // A rejected self-move leaves the node in place
+    assert_eq!(tree.move_subtree(2, 2), Err(TreeError::SelfParent(2)));
+    assert_eq!(tree.parent_map.get(&2), Some(&1));
+    assert!(!tree.nodes.get(&2).unwrap().children.contains(&2));
+}
+
+#[test]
+fn test_from_preorder() {
+    let mut tree = Tree::<u32>::from_preorder(&[
+        (0, 1, Permission::Public), // root node
+        (1, 2, Permission::Private),
+        (2, 3, Permission::Public),
+        (1, 4, Permission::Public),
+        (2, 5, Permission::Public),
+    ])
+    .unwrap();
+
+    // Build the same tree by hand
+    let mut expected = Tree::<u32>::new();
+    expected.add_node(1, Permission::Public, ()).unwrap();
+    expected.add_node(2, Permission::Private, ()).unwrap();
+    expected.add_node(3, Permission::Public, ()).unwrap();
+    expected.add_node(4, Permission::Public, ()).unwrap();
+    expected.add_node(5, Permission::Public, ()).unwrap();
+    expected.connect_nodes(1, 2).unwrap();
+    expected.connect_nodes(2, 3).unwrap();
+    expected.connect_nodes(1, 4).unwrap();
+    expected.connect_nodes(4, 5).unwrap();
+
+    assert_eq!(tree.parent_map, expected.parent_map);
+    for id in 1..=5 {
+        let node = tree.nodes.get(&id).unwrap();
+        let expected_node = expected.nodes.get(&id).unwrap();
+        assert_eq!(node.children, expected_node.children);
+        assert_eq!(node.permission, expected_node.permission);
+    }
+
+    // A freshly built tree has nothing to undo
+    assert!(!tree.undo());
+
+    // Skipping a level is rejected
+    let result =
+        Tree::<u32>::from_preorder(&[(0, 1, Permission::Public), (2, 2, Permission::Public)]);
+    assert_eq!(
+        result.unwrap_err(),
+        TreeError::InvalidDepth { id: 2, depth: 2 }
+    );
+
+    // Repeating an ID is rejected
+    let result =
+        Tree::<u32>::from_preorder(&[(0, 1, Permission::Public), (1, 1, Permission::Public)]);
+    assert_eq!(result.unwrap_err(), TreeError::NodeAlreadyExists(1));
+}
+
+#[test]
+fn test_access_report() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Private, ()).unwrap();
+    tree.add_node(6, Permission::Private, ()).unwrap(); // private root
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(3, 5).unwrap();
+
+    let report = tree.access_report();
+    assert_eq!(
+        report.levels,
+        vec![
+            DepthAccess {
+                public: 1,
+                protected: 0,
+                private: 1,
+                private_region_roots: vec![6],
+            },
+            DepthAccess {
+                public: 1,
+                protected: 0,
+                private: 1,
+                private_region_roots: vec![2],
+            },
+            // Node 4 is private by inheritance, so only node 5 starts a region here
+            DepthAccess {
+                public: 0,
+                protected: 0,
+                private: 2,
+                private_region_roots: vec![5],
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_set_subtree_permission() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+
+    // Connecting nodes; nodes 3 and 4 become private under node 2
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(3, 4).unwrap();
+    tree.connect_nodes(1, 5).unwrap();
+
+    // Opening up the whole branch under the public root changes all three nodes
+    assert_eq!(tree.set_subtree_permission(2, Permission::Public), Ok(3));
+    for id in [2, 3, 4] {
+        assert_eq!(tree.nodes.get(&id).unwrap().permission, Permission::Public);
+    }
+
+    // Only nodes that actually change are counted
+    assert_eq!(tree.set_subtree_permission(2, Permission::Public), Ok(0));
+    assert_eq!(
+        tree.set_subtree_permission(99, Permission::Public),
+        Err(TreeError::NodeNotFound(99))
+    );
+}
+
+#[test]
+fn test_remove_node() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+
+    // A node with children can't be removed
+    assert_eq!(tree.remove_node(2), Err(TreeError::HasChildren(2)));
+    assert!(tree.nodes.contains_key(&2));
+
+    // Removing a leaf detaches it from its parent
+    assert_eq!(tree.remove_node(3), Ok(()));
+    assert!(!tree.nodes.contains_key(&3));
+    assert!(!tree.parent_map.contains_key(&3));
+    assert!(!tree.nodes.get(&2).unwrap().children.contains(&3));
+
+    // Once its children are gone the root can be removed as well
+    tree.remove_node(2).unwrap();
+    assert_eq!(tree.remove_node(1), Ok(()));
+    assert!(tree.nodes.is_empty());
+    assert!(tree.parent_map.is_empty());
+
+    // Removing a node that doesn't exist is an error
+    assert_eq!(tree.remove_node(1), Err(TreeError::NodeNotFound(1)));
+}
+
+#[test]
+fn test_from_parent_array() {
+    // Children may appear before their parents
+    let mut tree = Tree::<u32>::from_parent_array(&[
+        (3, Some(2), Permission::Public),
+        (1, None, Permission::Public), // root node
+        (2, Some(1), Permission::Private),
+        (4, Some(1), Permission::Public),
+        (5, None, Permission::Public), // second root
+    ])
+    .unwrap();
+
+    assert_eq!(tree.parent_map.get(&3), Some(&2));
+    assert_eq!(tree.parent_map.get(&2), Some(&1));
+    assert_eq!(tree.parent_map.get(&4), Some(&1));
+    assert!(!tree.parent_map.contains_key(&5));
+
+    // Node 3 inherits the private permission of node 2
+    assert_eq!(tree.nodes.get(&3).unwrap().permission, Permission::Private);
+    assert_eq!(tree.nodes.get(&4).unwrap().permission, Permission::Public);
+
+    // A freshly built tree has nothing to undo
+    assert!(!tree.undo());
+
+    // A parent that isn't listed is rejected
+    let result = Tree::<u32>::from_parent_array(&[(1, Some(9), Permission::Public)]);
+    assert_eq!(result.unwrap_err(), TreeError::NodeNotFound(9));
+
+    // A loop of parents is rejected
+    let result = Tree::<u32>::from_parent_array(&[
+        (1, Some(3), Permission::Public),
+        (2, Some(1), Permission::Public),
+        (3, Some(2), Permission::Public),
+    ]);
+    assert!(matches!(
+        result.unwrap_err(),
+        TreeError::WouldCreateCycle { .. }
+    ));
+}
+
+#[test]
+fn test_remove_subtree() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+    tree.add_node(6, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(2, 5).unwrap();
+    tree.connect_nodes(5, 6).unwrap();
+
+    assert_eq!(tree.remove_subtree(2), Ok(4));
+
+    // No removed ID is left anywhere in the tree
+    let removed = [2, 4, 5, 6];
+    for id in removed {
+        assert!(!tree.nodes.contains_key(&id));
+        assert!(!tree.parent_map.contains_key(&id));
+    }
+    for node in tree.nodes.values() {
+        assert!(node.children.iter().all(|child| !removed.contains(child)));
+    }
+    assert!(tree
+        .parent_map
+        .values()
+        .all(|parent| !removed.contains(parent)));
+    assert_eq!(tree.nodes.get(&1).unwrap().children, HashSet::from([3]));
+
+    // Removing a whole tree from its root works too
+    assert_eq!(tree.remove_subtree(1), Ok(2));
+    assert!(tree.nodes.is_empty());
+    assert!(tree.parent_map.is_empty());
+
+    assert_eq!(tree.remove_subtree(1), Err(TreeError::NodeNotFound(1)));
+}
+
+#[test]
+fn test_detach_node() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Private, ()).unwrap(); // private root
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+
+    // Connecting nodes; everything below node 1 becomes private
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+
+    assert_eq!(tree.detach_node(2), Ok(()));
+
+    // Node 2 is now the root of its own tree with its subtree intact
+    assert!(!tree.parent_map.contains_key(&2));
+    assert!(!tree.nodes.get(&1).unwrap().children.contains(&2));
+    assert_eq!(tree.parent_map.get(&3), Some(&2));
+    assert_eq!(
+        tree.components(),
+        vec![HashSet::from([1]), HashSet::from([2, 3, 4])]
+    );
+
+    // Permissions are not recomputed after detaching
+    for id in [2, 3, 4] {
+        assert_eq!(tree.nodes.get(&id).unwrap().permission, Permission::Private);
+    }
+
+    assert_eq!(tree.detach_node(99), Err(TreeError::NodeNotFound(99)));
+}
+
+#[test]
+fn test_set_permission() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap(); // private node
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Private, ()).unwrap(); // declared private grandchild
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(3, 4).unwrap();
+    tree.connect_nodes(3, 5).unwrap();
+    assert_eq!(tree.nodes.get(&4).unwrap().permission, Permission::Private);
+
+    // Flipping node 2 back to public restores nodes that were only private by inheritance
+    assert_eq!(tree.set_permission(2, Permission::Public), Ok(()));
+    assert_eq!(tree.nodes.get(&2).unwrap().permission, Permission::Public);
+    assert_eq!(tree.nodes.get(&3).unwrap().permission, Permission::Public);
+    assert_eq!(tree.nodes.get(&4).unwrap().permission, Permission::Public);
+
+    // A node declared private stays private
+    assert_eq!(tree.nodes.get(&5).unwrap().permission, Permission::Private);
+
+    // Making the root private pushes it through the whole tree again
+    tree.set_permission(1, Permission::Private).unwrap();
+    assert!(tree
+        .nodes
+        .values()
+        .all(|node| node.permission == Permission::Private));
+    assert_eq!(
+        tree.nodes.get(&4).unwrap().declared_permission,
+        Permission::Public
+    );
+
+    assert_eq!(
+        tree.set_permission(99, Permission::Public),
+        Err(TreeError::NodeNotFound(99))
+    );
+}
+
+#[test]
+fn test_dominated_by() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap(); // gateway node
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+    tree.add_node(6, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 6).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(4, 5).unwrap();
+
+    // Everything below the gateway is dominated by it
+    assert_eq!(tree.dominated_by(2), vec![3, 4, 5]);
+    assert_eq!(tree.count_dominated_by(2), 3);
+
+    // Leaves and unknown nodes dominate nothing
+    assert!(tree.dominated_by(5).is_empty());
+    assert!(tree.dominated_by(99).is_empty());
+    assert_eq!(tree.count_dominated_by(99), 0);
+
+    // A child listed under the gateway whose parent is elsewhere isn't dominated
+    tree.nodes.get_mut(&2).unwrap().children.insert(6);
+    assert_eq!(tree.dominated_by(2), vec![3, 4, 5]);
+}
+
+#[test]
+fn test_move_subtree_restores_public() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap(); // private node
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Private, ()).unwrap(); // declared private child
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(3, 4).unwrap();
+    tree.connect_nodes(3, 5).unwrap();
+
+    // Moving under the private node makes the whole subtree private
+    tree.move_subtree(3, 2).unwrap();
+    assert_eq!(tree.nodes.get(&3).unwrap().permission, Permission::Private);
+    assert_eq!(tree.nodes.get(&4).unwrap().permission, Permission::Private);
+
+    // Moving back under the public root restores the inherited-only nodes
+    tree.move_subtree(3, 1).unwrap();
+    assert_eq!(tree.nodes.get(&3).unwrap().permission, Permission::Public);
+    assert_eq!(tree.nodes.get(&4).unwrap().permission, Permission::Public);
+
+    // The declared private node stays private
+    assert_eq!(tree.nodes.get(&5).unwrap().permission, Permission::Private);
+}
+
+#[test]
+fn test_privacy_skew() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Private, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(4, 5).unwrap();
+
+    let skew = tree.privacy_skew();
+
+    // Node 2's subtree has two private nodes out of four
+    assert_eq!(skew[&2], 0.5);
+    assert_eq!(skew[&1], 0.4);
+    assert_eq!(skew[&3], 1.0);
+    assert_eq!(skew.len(), 5);
+}
+
+#[test]
+fn test_spanning_subtree() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+    tree.add_node(6, Permission::Public, ()).unwrap();
+    tree.add_node(7, Permission::Public, ()).unwrap();
+    tree.add_node(8, Permission::Public, ()).unwrap(); // separate root
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(3, 5).unwrap();
+    tree.connect_nodes(4, 6).unwrap();
+    tree.connect_nodes(4, 7).unwrap();
+
+    // Leaves 5 and 6 meet at node 2; node 1 and leaf 7 aren't on the paths
+    let spanning = tree.spanning_subtree(&[5, 6]).unwrap();
+    let mut ids: Vec<u32> = spanning.nodes.keys().copied().collect();
+    ids.sort();
+    assert_eq!(ids, vec![2, 3, 4, 5, 6]);
+
+    // The common ancestor is the root and only the connecting edges remain
+    assert!(!spanning.parent_map.contains_key(&2));
+    assert_eq!(spanning.parent_map.get(&5), Some(&3));
+    assert_eq!(spanning.nodes.get(&4).unwrap().children, HashSet::from([6]));
+    assert_eq!(
+        spanning.nodes.get(&5).unwrap().permission,
+        Permission::Private
+    );
+
+    // Nodes in separate trees or unknown nodes have no spanning subtree
+    assert!(tree.spanning_subtree(&[5, 8]).is_none());
+    assert!(tree.spanning_subtree(&[5, 99]).is_none());
+    assert!(tree.spanning_subtree(&[]).is_none());
+}
+
+#[test]
+fn test_own_and_inherited_tags() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+
+    // Tags added before connecting are passed down once nodes are connected
+    tree.add_tag_to_node(1, "team").unwrap();
+    tree.add_tag_to_node(3, "draft").unwrap();
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+
+    let node = tree.nodes.get(&3).unwrap();
+    assert_eq!(node.own_tags, HashSet::from(["draft".to_string()]));
+    assert_eq!(node.inherited_tags, HashSet::from(["team".to_string()]));
+    assert_eq!(
+        node.effective_tags(),
+        HashSet::from(["team".to_string(), "draft".to_string()])
+    );
+
+    // Tags added later reach the whole subtree but aren't owned by it
+    tree.add_tag_to_node(2, "reviewed").unwrap();
+    let node = tree.nodes.get(&3).unwrap();
+    assert!(node.effective_tags().contains("reviewed"));
+    assert!(!node.own_tags.contains("reviewed"));
+    assert!(!tree
+        .nodes
+        .get(&1)
+        .unwrap()
+        .effective_tags()
+        .contains("reviewed"));
+
+    // Moving a subtree recomputes its inherited tags from the new parent
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.move_subtree(3, 4).unwrap();
+    assert_eq!(
+        tree.nodes.get(&3).unwrap().effective_tags(),
+        HashSet::from(["draft".to_string()])
+    );
+
+    assert_eq!(
+        tree.add_tag_to_node(99, "team"),
+        Err(TreeError::NodeNotFound(99))
+    );
+}
+
+#[test]
+fn test_remove_tag_from_node() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(3, 4).unwrap();
+
+    // Node 2 and its grandchild 4 both declare "draft"; the root declares "team"
+    tree.add_tag_to_node(1, "team").unwrap();
+    tree.add_tag_to_node(2, "draft").unwrap();
+    tree.add_tag_to_node(4, "draft").unwrap();
+
+    // Removing the tag from node 2 retracts it from node 3 but not from node 4
+    tree.remove_tag_from_node(2, "draft").unwrap();
+    assert!(!tree.nodes[&2].effective_tags().contains("draft"));
+    assert!(!tree.nodes[&3].effective_tags().contains("draft"));
+    assert!(tree.nodes[&4].effective_tags().contains("draft"));
+
+    // A tag inherited from an ancestor can't be removed from a descendant
+    tree.remove_tag_from_node(3, "team").unwrap();
+    assert!(tree.nodes[&3].effective_tags().contains("team"));
+    assert!(tree.nodes[&4].effective_tags().contains("team"));
+
+    assert_eq!(
+        tree.remove_tag_from_node(99, "team"),
+        Err(TreeError::NodeNotFound(99))
+    );
+}
+
+#[test]
+fn test_find_nodes_by_tag() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap(); // separate root
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 4).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+
+    // Tagging node 2 also tags node 3 through inheritance
+    tree.add_tag_to_node(2, "archive").unwrap();
+    tree.add_tag_to_node(5, "archive").unwrap();
+    assert_eq!(tree.find_nodes_by_tag("archive"), vec![2, 3, 5]);
+
+    // Tags that appear nowhere find nothing
+    assert!(tree.find_nodes_by_tag("missing").is_empty());
+}
+
+#[test]
+fn test_new_roots_if_removed() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(3, 5).unwrap();
+
+    // Only the direct children would become roots
+    assert_eq!(tree.new_roots_if_removed(2), vec![3, 4]);
+    assert!(tree.new_roots_if_removed(5).is_empty());
+    assert!(tree.new_roots_if_removed(99).is_empty());
+
+    // Nothing is actually removed
+    assert_eq!(tree.parent_map.get(&3), Some(&2));
+}
+
+#[test]
+fn test_get_ancestors() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes and connecting them into a 5-level chain
+    for id in 1..=5 {
+        tree.add_node(id, Permission::Public, ()).unwrap();
+    }
+    for id in 1..5 {
+        tree.connect_nodes(id, id + 1).unwrap();
+    }
+
+    // Ancestors run from the parent up to the root
+    assert_eq!(tree.get_ancestors(5), vec![4, 3, 2, 1]);
+    assert_eq!(tree.get_ancestors(3), vec![2, 1]);
+
+    // Roots and unknown nodes have no ancestors
+    assert!(tree.get_ancestors(1).is_empty());
+    assert!(tree.get_ancestors(99).is_empty());
+}
+
+#[test]
+fn test_op_log_replay() {
+    let mut primary = Tree::<u32, String>::new();
+    primary.enable_op_log();
+
+    // Mutate the primary tree
+    primary
+        .add_node(1, Permission::Public, "root".to_string())
+        .unwrap(); // root node
+    primary
+        .add_node(2, Permission::Public, "docs".to_string())
+        .unwrap();
+    primary
+        .add_node(3, Permission::Private, "secrets".to_string())
+        .unwrap();
+    primary
+        .add_node(4, Permission::Public, "tmp".to_string())
+        .unwrap();
+    primary.connect_nodes(1, 2).unwrap();
+    primary.connect_nodes(1, 3).unwrap();
+    primary.connect_nodes(2, 4).unwrap();
+    primary.add_tag_to_node(2, "shared").unwrap();
+    primary.move_subtree(2, 3).unwrap();
+    primary.set_permission(3, Permission::Public).unwrap();
+    primary.remove_node(4).unwrap();
+    primary.set_data(2, "manual".to_string()).unwrap();
+    assert_eq!(
+        primary.set_subtree_permission(3, Permission::Protected),
+        Ok(2)
+    );
+    assert_eq!(primary.ensure_roots_permission(Permission::Protected), 1);
+
+    // Failed mutations are not recorded
+    assert!(primary.connect_nodes(1, 1).is_err());
+    assert!(primary.set_data(99, String::new()).is_err());
+
+    let ops = primary.take_ops();
+    assert_eq!(ops.len(), 15);
+    assert_eq!(
+        ops[4],
+        TreeOp::Connect {
+            parent: 1,
+            child: 2
+        }
+    );
+    assert_eq!(
+        ops[0],
+        TreeOp::AddNode {
+            id: 1,
+            permission: Permission::Public,
+            data: "root".to_string()
+        }
+    );
+    assert!(primary.take_ops().is_empty());
+
+    // Replaying onto an empty tree reproduces the primary
+    let mut replica = Tree::<u32, String>::new();
+    replica.apply_ops(&ops).unwrap();
+    assert_eq!(replica.nodes, primary.nodes);
+    assert_eq!(replica.parent_map, primary.parent_map);
+    assert_eq!(replica.get_data(2), Some(&"manual".to_string()));
+
+    // Without logging enabled nothing is recorded
+    assert!(replica.take_ops().is_empty());
+}
+
+#[test]
+fn test_op_log_replays_bulk_helpers() {
+    let mut primary = Tree::<u32, String>::new();
+    primary.enable_op_log();
+
+    // Adding nodes as a chain 1 -> 2 -> ... -> 6
+    for id in 1..=6 {
+        primary
+            .add_node(id, Permission::Public, format!("node {}", id))
+            .unwrap();
+        if id > 1 {
+            primary.connect_nodes(id - 1, id).unwrap();
+        }
+    }
+    primary.add_tag_to_node(3, "x").unwrap();
+    primary.add_tag_to_node(6, "z").unwrap();
+    primary.set_permission(6, Permission::Protected).unwrap();
+
+    // Each bulk helper is recorded as single-node ops
+    primary.flatten_chain(1, 2).unwrap();
+    primary
+        .for_each_mut(1, |node| {
+            if *node.id == 3 {
+                node.own_tags.remove("x");
+            }
+            if *node.id == 4 {
+                *node.declared_permission = Permission::Private;
+                node.own_tags.insert("y".to_string());
+            }
+            node.data.push('!');
+        })
+        .unwrap();
+    assert_eq!(primary.collapse_single_child_chains(1), Ok(1));
+
+    let mut other = Tree::<u32, String>::new();
+    other
+        .add_node(10, Permission::Public, "other".to_string())
+        .unwrap(); // root node
+    other
+        .add_node(11, Permission::Private, "other child".to_string())
+        .unwrap();
+    other.connect_nodes(10, 11).unwrap();
+    other.add_tag_to_node(10, "t").unwrap();
+    primary.merge(other, Some(5)).unwrap();
+
+    // Replaying onto an empty tree reproduces the primary
+    let mut replica = Tree::<u32, String>::new();
+    replica.apply_ops(&primary.take_ops()).unwrap();
+    assert_eq!(replica.nodes, primary.nodes);
+    assert_eq!(replica.parent_map, primary.parent_map);
+}
+
+#[test]
+fn test_get_descendants() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+    tree.add_node(6, Permission::Public, ()).unwrap();
+
+    // Connecting nodes, children added out of order
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(3, 4).unwrap();
+    tree.connect_nodes(2, 6).unwrap();
+    tree.connect_nodes(2, 5).unwrap();
+
+    // Level by level, each node's children in ascending order
+    assert_eq!(tree.get_descendants(1), vec![2, 3, 5, 6, 4]);
+    assert_eq!(tree.get_descendants(2), vec![5, 6]);
+
+    // Leaves and unknown nodes have no descendants
+    assert!(tree.get_descendants(4).is_empty());
+    assert!(tree.get_descendants(99).is_empty());
+}
+
+#[test]
+fn test_get_children() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    for id in [7, 3, 9, 5] {
+        tree.add_node(id, Permission::Public, ()).unwrap();
+    }
+
+    // Connecting children in a scrambled order
+    for id in [7, 3, 9, 5] {
+        tree.connect_nodes(1, id).unwrap();
+    }
+
+    // Children always come back sorted
+    assert_eq!(tree.get_children(1), vec![3, 5, 7, 9]);
+
+    // Leaves and unknown nodes have no children
+    assert!(tree.get_children(3).is_empty());
+    assert!(tree.get_children(99).is_empty());
+}
+
+#[test]
+fn test_get_siblings() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 4).unwrap();
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 5).unwrap();
+
+    // Node 3 has two siblings, node 5 has none
+    assert_eq!(tree.get_siblings(3), vec![2, 4]);
+    assert!(tree.get_siblings(5).is_empty());
+
+    // A root has no parent and so no siblings
+    assert!(tree.get_siblings(1).is_empty());
+}
+
+#[test]
+fn test_depth() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 5).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(3, 4).unwrap();
+
+    assert_eq!(tree.depth(1), Some(0));
+    assert_eq!(tree.depth(2), Some(1));
+    assert_eq!(tree.depth(5), Some(1));
+    assert_eq!(tree.depth(3), Some(2));
+    assert_eq!(tree.depth(4), Some(3));
+    assert_eq!(tree.depth(99), None);
+}
+
+#[test]
+fn test_get_roots() {
+    let mut tree = Tree::<u32>::new();
+    assert!(tree.get_roots().is_empty());
+
+    // Adding nodes
+    tree.add_node(5, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap(); // root node
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(1, Permission::Public, ()).unwrap(); // never connected
+
+    // Connecting nodes
+    tree.connect_nodes(5, 2).unwrap();
+    tree.connect_nodes(3, 4).unwrap();
+
+    // Both tree roots and the unconnected node are returned in order
+    assert_eq!(tree.get_roots(), vec![1, 3, 5]);
+}
+
+#[test]
+fn test_lowest_common_ancestor() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+    tree.add_node(6, Permission::Public, ()).unwrap();
+    tree.add_node(7, Permission::Public, ()).unwrap(); // separate root
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(2, 5).unwrap();
+    tree.connect_nodes(4, 6).unwrap();
+
+    // Within a shared subtree
+    assert_eq!(tree.lowest_common_ancestor(6, 5), Some(2));
+    assert_eq!(tree.lowest_common_ancestor(6, 3), Some(1));
+
+    // A node is its own ancestor
+    assert_eq!(tree.lowest_common_ancestor(2, 6), Some(2));
+    assert_eq!(tree.lowest_common_ancestor(4, 4), Some(4));
+
+    // Separate trees or unknown nodes have no common ancestor
+    assert_eq!(tree.lowest_common_ancestor(6, 7), None);
+    assert_eq!(tree.lowest_common_ancestor(6, 99), None);
+}
+
+#[test]
+fn test_path_between() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+    tree.add_node(6, Permission::Public, ()).unwrap(); // separate root
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(3, 5).unwrap();
+
+    // Up through the common ancestor and back down
+    assert_eq!(tree.path_between(4, 5), Some(vec![4, 2, 1, 3, 5]));
+    assert_eq!(tree.path_between(5, 4), Some(vec![5, 3, 1, 2, 4]));
+
+    // Straight down when one node is an ancestor of the other
+    assert_eq!(tree.path_between(1, 4), Some(vec![1, 2, 4]));
+    assert_eq!(tree.path_between(4, 4), Some(vec![4]));
+
+    // Nodes in separate trees have no path
+    assert_eq!(tree.path_between(4, 6), None);
+}
+
+#[test]
+fn test_len_and_is_empty() {
+    let mut tree = Tree::<u32>::new();
+    assert!(tree.is_empty());
+    assert_eq!(tree.len(), 0);
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.connect_nodes(1, 2).unwrap();
+    assert!(!tree.is_empty());
+    assert_eq!(tree.len(), 3);
+
+    // Removing nodes
+    tree.remove_node(2).unwrap();
+    assert_eq!(tree.len(), 2);
+    tree.remove_node(1).unwrap();
+    tree.remove_node(3).unwrap();
+    assert!(tree.is_empty());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_round_trip() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap(); // separate root
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.add_tag_to_node(1, "team").unwrap();
+
+    let json = serde_json::to_string(&tree).unwrap();
+    let loaded: Tree = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(loaded.nodes, tree.nodes);
+    assert_eq!(loaded.parent_map, tree.parent_map);
+    assert_eq!(loaded.nodes[&3].permission, Permission::Private);
+    assert!(loaded.nodes[&3].effective_tags().contains("team"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_json_round_trip_and_validation() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap();
+    tree.connect_nodes(1, 2).unwrap();
+
+    let json = tree.to_json().unwrap();
+    let loaded = Tree::<u32>::from_json(&json).unwrap();
+    assert_eq!(loaded.nodes, tree.nodes);
+    assert_eq!(loaded.parent_map, tree.parent_map);
+
+    // Node 1 claims a child that was never added
+    let dangling = r#"{
+        "nodes": {
+            "1": {
+                "id": 1,
+                "permission": "Public",
+                "declared_permission": "Public",
+                "own_tags": [],
+                "inherited_tags": [],
+                "children": [7],
+                "data": null
+            }
+        },
+        "parent_map": { "7": 1 }
+    }"#;
+    assert_eq!(
+        Tree::<u32>::from_json(dangling).unwrap_err(),
+        TreeError::DanglingChild {
+            parent: 1,
+            child: 7
+        }
+    );
+
+    // A node stored under a key other than its own ID
+    let mismatched = r#"{
+        "nodes": {
+            "1": {
+                "id": 7,
+                "permission": "Private",
+                "declared_permission": "Private",
+                "own_tags": [],
+                "inherited_tags": [],
+                "children": [],
+                "data": null
+            }
+        },
+        "parent_map": {}
+    }"#;
+    assert_eq!(
+        Tree::<u32>::from_json(mismatched).unwrap_err(),
+        TreeError::MismatchedId { key: 1, id: 7 }
+    );
+
+    // Syntax errors are reported as JSON errors
+    assert!(matches!(
+        Tree::<u32>::from_json("{ not json"),
+        Err(TreeError::Json(_))
+    ));
+
+    // A child edited to look public under a private parent is recomputed
+    let tampered = r#"{
+        "nodes": {
+            "1": {
+                "id": 1,
+                "permission": "Private",
+                "declared_permission": "Private",
+                "own_tags": ["secret"],
+                "inherited_tags": [],
+                "children": [2],
+                "data": null
+            },
+            "2": {
+                "id": 2,
+                "permission": "Public",
+                "declared_permission": "Public",
+                "own_tags": [],
+                "inherited_tags": [],
+                "children": [],
+                "data": null
+            }
+        },
+        "parent_map": { "2": 1 }
+    }"#;
+    let loaded = Tree::<u32>::from_json(tampered).unwrap();
+    assert_eq!(loaded.nodes[&2].permission, Permission::Private);
+    assert!(loaded.nodes[&2].inherited_tags.contains("secret"));
+}
+
+#[test]
+fn test_to_dot() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.add_tag_to_node(3, "docs").unwrap();
+
+    let dot = tree.to_dot();
+    assert!(dot.starts_with("digraph tree {"));
+    assert!(dot.contains("\"1\" [label=\"1 (Public)\"];"));
+    assert!(dot.contains("\"2\" [label=\"2 (Private)\", style=filled, fillcolor=gray];"));
+    assert!(dot.contains("\"3\" [label=\"3 (Public)\\ndocs\"];"));
+    assert!(dot.contains("\"1\" -> \"2\";"));
+    assert!(dot.contains("\"1\" -> \"3\";"));
+    assert!(!dot.contains("\"2\" -> \"3\";"));
+    assert!(dot.trim_end().ends_with('}'));
+
+    // Quotes and backslashes in tags are escaped inside the label
+    tree.add_tag_to_node(2, "say \"hi\" \\ bye").unwrap();
+    assert!(tree
+        .to_dot()
+        .contains("[label=\"2 (Private)\\nsay \\\"hi\\\" \\\\ bye\", style=filled"));
+
+    // String IDs with spaces, slashes or quotes are quoted and escaped
+    let mut tree = Tree::<String>::new();
+    tree.add_node("team a".to_string(), Permission::Public, ())
+        .unwrap(); // root node
+    tree.add_node("/docs/api".to_string(), Permission::Public, ())
+        .unwrap();
+    tree.add_node("say \"hi\"".to_string(), Permission::Public, ())
+        .unwrap();
+    tree.connect_nodes("team a".to_string(), "/docs/api".to_string())
+        .unwrap();
+    tree.connect_nodes("team a".to_string(), "say \"hi\"".to_string())
+        .unwrap();
+
+    let dot = tree.to_dot();
+    assert!(dot.contains("\"team a\" [label=\"team a (Public)\"];"));
+    assert!(dot.contains("\"team a\" -> \"/docs/api\";"));
+    assert!(dot.contains("\"team a\" -> \"say \\\"hi\\\"\";"));
+    assert!(dot.contains("[label=\"say \\\"hi\\\" (Public)\"];"));
+}
+
+#[test]
+fn test_bfs() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    for id in 1..=7 {
+        tree.add_node(id, Permission::Public, ()).unwrap();
+    }
+
+    // Connecting nodes, with children added out of order
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 5).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(3, 6).unwrap();
+    tree.connect_nodes(4, 7).unwrap();
+
+    let order: Vec<u32> = tree.bfs(1).collect();
+    assert_eq!(order, vec![1, 2, 3, 4, 5, 6, 7]);
+
+    // Any subtree can be the starting point
+    assert_eq!(tree.bfs(2).collect::<Vec<_>>(), vec![2, 4, 5, 7]);
+    assert_eq!(tree.bfs(42).count(), 0);
+}
+
+#[test]
+fn test_dfs() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    for id in 1..=7 {
+        tree.add_node(id, Permission::Public, ()).unwrap();
+    }
+
+    // Connecting nodes, with children added out of order
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 5).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(3, 6).unwrap();
+    tree.connect_nodes(4, 7).unwrap();
+
+    let order: Vec<u32> = tree.dfs(1).collect();
+    assert_eq!(order, vec![1, 2, 4, 7, 5, 3, 6]);
+
+    assert_eq!(tree.dfs(3).collect::<Vec<_>>(), vec![3, 6]);
+    assert_eq!(tree.dfs(42).count(), 0);
+}
+
+#[test]
+fn test_leaves() {
+    let mut tree = Tree::<u32>::new();
+    assert!(tree.leaves().is_empty());
+
+    // Adding nodes
+    for id in 1..=7 {
+        tree.add_node(id, Permission::Public, ()).unwrap();
+    }
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(2, 5).unwrap();
+    tree.connect_nodes(3, 6).unwrap();
+
+    // Node 7 is a lone root and counts as a leaf too
+    assert_eq!(tree.leaves(), vec![4, 5, 6, 7]);
+}
+
+#[test]
+fn test_connect_rejects_cycle() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+
+    // Connecting nodes into a chain 1 -> 2 -> 3
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+
+    // Detach node 2 from the root, then try to hang it under its own child
+    tree.detach_node(2).unwrap();
+    assert_eq!(
+        tree.connect_nodes(3, 2),
+        Err(TreeError::WouldCreateCycle { node: 2, target: 3 })
+    );
+
+    // No edge was added
+    assert!(tree.get_children(3).is_empty());
+    assert_eq!(tree.parent_map.get(&2), None);
+    assert_eq!(tree.get_children(2), vec![3]);
+}
+
+// Build a public chain 1 -> 2 -> ... -> len through the fields directly, since linking
+// it through `connect_nodes` walks the whole chain on every call
+fn long_chain(len: u32) -> Tree {
+    let mut tree = Tree::<u32>::new();
+    for id in 1..=len {
+        tree.nodes.insert(
+            id,
+            TreeNode {
+                id,
+                permission: Permission::Public,
+                declared_permission: Permission::Public,
+                own_tags: HashSet::new(),
+                inherited_tags: HashSet::new(),
+                children: if id < len {
+                    HashSet::from([id + 1])
+                } else {
+                    HashSet::new()
+                },
+                data: (),
+            },
+        );
+        if id > 1 {
+            tree.parent_map.insert(id, id - 1);
+        }
+    }
+    tree
+}
+
+#[test]
+fn test_update_permission_on_deep_chain() {
+    let mut tree = long_chain(50_000);
+
+    // Making a node near the top private reaches all the way down
+    tree.set_permission(2, Permission::Private).unwrap();
+    assert_eq!(tree.nodes[&50_000].permission, Permission::Private);
+    assert_eq!(tree.nodes[&1].permission, Permission::Public);
+
+    tree.set_permission(2, Permission::Public).unwrap();
+    assert_eq!(tree.nodes[&50_000].permission, Permission::Public);
+}
+
+#[test]
+fn test_update_tags_on_deep_chain() {
+    let mut tree = long_chain(50_000);
+
+    // A tag on the root reaches the bottom of the chain
+    tree.add_tag_to_node(1, "archive").unwrap();
+    assert!(tree.nodes[&50_000].effective_tags().contains("archive"));
+    assert!(tree.nodes[&50_000].own_tags.is_empty());
+}
+
+#[test]
+fn test_update_tags_repeated_on_deep_chain() {
+    let mut tree = long_chain(50_000);
+
+    // Each tag pushes a full walk down the chain, which must stay fast
+    for id in (1..=50_000).step_by(5_000) {
+        tree.add_tag_to_node(id, &format!("tag-{}", id)).unwrap();
+    }
+
+    let bottom = tree.nodes[&50_000].effective_tags();
+    assert_eq!(bottom.len(), 10);
+    assert!(bottom.contains("tag-1"));
+    assert!(bottom.contains("tag-45001"));
+    assert!(!tree.nodes[&2].effective_tags().contains("tag-5001"));
+}
+
+#[test]
+fn test_effective_permission() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(1, 4).unwrap();
+
+    // Node 3 is declared public but sits under a private node
+    assert_eq!(tree.nodes[&3].declared_permission, Permission::Public);
+    assert_eq!(tree.effective_permission(3), Some(Permission::Private));
+    assert_eq!(tree.effective_permission(2), Some(Permission::Private));
+    assert_eq!(tree.effective_permission(4), Some(Permission::Public));
+    assert_eq!(tree.effective_permission(42), None);
+
+    // Agrees with the stored value everywhere
+    for (id, node) in &tree.nodes {
+        assert_eq!(
+            tree.effective_permission(*id),
+            Some(node.permission.clone())
+        );
+    }
+}
+
+#[test]
+fn test_protected_permission() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Protected, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Private, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(4, 5).unwrap();
+
+    assert!(Permission::Public < Permission::Protected);
+    assert!(Permission::Protected < Permission::Private);
+
+    // A protected parent raises public children but doesn't lower private ones
+    assert_eq!(tree.nodes[&1].permission, Permission::Public);
+    assert_eq!(tree.nodes[&3].permission, Permission::Protected);
+    assert_eq!(tree.nodes[&4].permission, Permission::Private);
+    assert_eq!(tree.nodes[&5].permission, Permission::Private);
+    assert_eq!(tree.effective_permission(3), Some(Permission::Protected));
+
+    // Relaxing the parent lets the children fall back to their own levels
+    tree.set_permission(2, Permission::Public).unwrap();
+    assert_eq!(tree.nodes[&3].permission, Permission::Public);
+    assert_eq!(tree.nodes[&4].permission, Permission::Private);
+
+    let report = tree.access_report();
+    assert_eq!(report.levels[0].protected, 0);
+    tree.set_permission(1, Permission::Protected).unwrap();
+    assert_eq!(tree.access_report().levels[2].protected, 1);
+}
+
+#[test]
+fn test_string_ids() {
+    let mut tree = Tree::<String>::new();
+
+    // Adding nodes
+    tree.add_node("root".to_string(), Permission::Public, ())
+        .unwrap(); // root node
+    tree.add_node("docs".to_string(), Permission::Private, ())
+        .unwrap();
+    tree.add_node("guide".to_string(), Permission::Public, ())
+        .unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes("root".to_string(), "docs".to_string())
+        .unwrap();
+    tree.connect_nodes("docs".to_string(), "guide".to_string())
+        .unwrap();
+
+    assert_eq!(tree.nodes["guide"].permission, Permission::Private);
+    assert_eq!(
+        tree.get_ancestors("guide".to_string()),
+        vec!["docs".to_string(), "root".to_string()]
+    );
+    assert_eq!(
+        tree.connect_nodes("guide".to_string(), "root".to_string()),
+        Err(TreeError::WouldCreateCycle {
+            node: "root".to_string(),
+            target: "guide".to_string()
+        })
+    );
+}
+
+// An ID type with neither `Ord` nor `Display`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key(&'static str);
+
+#[test]
+fn test_unordered_ids() {
+    let mut tree = Tree::<Key>::new();
+
+    // Adding nodes
+    tree.add_node(Key("root"), Permission::Public, ()).unwrap(); // root node
+    tree.add_node(Key("docs"), Permission::Private, ()).unwrap();
+    tree.add_node(Key("guide"), Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(Key("root"), Key("docs")).unwrap();
+    tree.connect_nodes(Key("docs"), Key("guide")).unwrap();
+
+    // Mutations and unordered queries work without `Ord` or `Display`
+    assert_eq!(tree.get_permission(Key("guide")), Some(Permission::Private));
+    tree.set_permission(Key("docs"), Permission::Public)
+        .unwrap();
+    assert_eq!(tree.get_permission(Key("guide")), Some(Permission::Public));
+    tree.move_subtree(Key("guide"), Key("root")).unwrap();
+    assert!(tree.undo());
+    assert_eq!(tree.parent_map.get(&Key("guide")), Some(&Key("docs")));
+    assert_eq!(tree.remove_subtree(Key("docs")), Ok(2));
+    assert_eq!(tree.validate(), Ok(()));
+}
+
+#[test]
+fn test_node_payloads() {
+    let mut tree = Tree::<u32, String>::new();
+
+    // Adding nodes with a file name as payload
+    tree.add_node(1, Permission::Public, "/".to_string())
+        .unwrap(); // root node
+    tree.add_node(2, Permission::Public, "home".to_string())
+        .unwrap();
+    tree.add_node(3, Permission::Private, "secrets".to_string())
+        .unwrap();
+    tree.add_node(4, Permission::Public, "notes.txt".to_string())
+        .unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+
+    tree.get_data_mut(4).unwrap().push_str(".bak");
+
+    // Payloads travel with their nodes, while permissions still follow the new parent
+    tree.move_subtree(2, 3).unwrap();
+    assert_eq!(tree.get_data(2), Some(&"home".to_string()));
+    assert_eq!(tree.get_data(4), Some(&"notes.txt.bak".to_string()));
+    assert_eq!(tree.nodes[&4].permission, Permission::Private);
+
+    assert_eq!(tree.get_data(99), None);
+    assert!(tree.get_data_mut(99).is_none());
+}
+
+#[test]
+fn test_validate() {
+    let build = || {
+        let mut tree = Tree::<u32>::new();
+        for id in 1..=4 {
+            tree.add_node(id, Permission::Public, ()).unwrap();
+        }
+        tree.connect_nodes(1, 2).unwrap();
+        tree.connect_nodes(1, 3).unwrap();
+        tree.connect_nodes(3, 4).unwrap();
+        tree
+    };
+    assert_eq!(build().validate(), Ok(()));
+
+    // A child that was never added
+    let mut tree = build();
+    tree.nodes.get_mut(&2).unwrap().children.insert(9);
+    assert_eq!(
+        tree.validate(),
+        Err(TreeError::DanglingChild {
+            parent: 2,
+            child: 9
+        })
+    );
+
+    // A parent map entry the parent doesn't list
+    let mut tree = build();
+    tree.nodes.get_mut(&3).unwrap().children.remove(&4);
+    assert_eq!(
+        tree.validate(),
+        Err(TreeError::InconsistentParent {
+            child: 4,
+            parent: 3
+        })
+    );
+
+    // A node listed as a child of two parents
+    let mut tree = build();
+    tree.nodes.get_mut(&2).unwrap().children.insert(4);
+    assert_eq!(
+        tree.validate(),
+        Err(TreeError::InconsistentParent {
+            child: 4,
+            parent: 2
+        })
+    );
+
+    // A loop that is consistent on both sides
+    let mut tree = build();
+    tree.nodes.get_mut(&4).unwrap().children.insert(1);
+    tree.parent_map.insert(1, 4);
+    assert!(matches!(tree.validate(), Err(TreeError::Cycle(_))));
+
+    // A node whose ID doesn't match the key it is stored under
+    let mut tree = build();
+    tree.nodes.get_mut(&2).unwrap().id = 7;
+    assert_eq!(
+        tree.validate(),
+        Err(TreeError::MismatchedId { key: 2, id: 7 })
+    );
+    assert_eq!(tree.private_leaves(), Vec::<u32>::new());
+}
+
+#[test]
+fn test_undo_redo_move_subtree() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+
+    let nodes_before = tree.nodes.clone();
+    let parents_before = tree.parent_map.clone();
+
+    // Moving the public subtree under the private node makes it private
+    tree.move_subtree(2, 3).unwrap();
+    assert_eq!(tree.nodes[&4].permission, Permission::Private);
+    let nodes_after = tree.nodes.clone();
+
+    // Undo puts back both the old parent link and the old permissions
+    assert!(tree.undo());
+    assert_eq!(tree.nodes, nodes_before);
+    assert_eq!(tree.parent_map, parents_before);
+
+    // Redo moves it again
+    assert!(tree.redo());
+    assert_eq!(tree.nodes, nodes_after);
+    assert!(!tree.redo());
+}
+
+#[test]
+fn test_undo_redo_history() {
+    let mut tree = Tree::<u32>::new();
+    assert!(!tree.undo());
+
+    // Adding nodes
+    tree.add_node(1, Permission::Private, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.connect_nodes(1, 2).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.remove_node(3).unwrap();
+
+    // Undo the removal, then the connection that made node 3 private
+    assert!(tree.undo());
+    assert_eq!(tree.get_children(2), vec![3]);
+    assert_eq!(tree.nodes[&3].permission, Permission::Private);
+    assert!(tree.undo());
+    assert_eq!(tree.parent_map.get(&3), None);
+    assert_eq!(tree.nodes[&3].permission, Permission::Public);
+    assert!(tree.undo());
+    assert!(!tree.nodes.contains_key(&3));
+
+    // A new mutation drops what was undone
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    assert!(!tree.redo());
+
+    // Mutations the history can't reverse clear it entirely
+    tree.set_permission(4, Permission::Private).unwrap();
+    assert!(!tree.undo());
+    assert_eq!(tree.validate(), Ok(()));
+}
+
+#[test]
+fn test_undo_disabled_while_logging() {
+    let mut primary = Tree::<u32>::new();
+    primary.add_node(1, Permission::Public, ()).unwrap(); // root node
+    primary.enable_op_log();
+
+    // Mutate the primary tree
+    primary.add_node(2, Permission::Public, ()).unwrap();
+    primary.connect_nodes(1, 2).unwrap();
+
+    // Undo and redo would leave the replica behind, so they do nothing
+    assert!(!primary.undo());
+    assert!(!primary.redo());
+    assert_eq!(primary.parent_map.get(&2), Some(&1));
+
+    // Replaying onto a copy of the starting state reproduces the primary
+    let mut replica = Tree::<u32>::new();
+    replica.add_node(1, Permission::Public, ()).unwrap();
+    replica.apply_ops(&primary.take_ops()).unwrap();
+    assert_eq!(replica.nodes, primary.nodes);
+    assert_eq!(replica.parent_map, primary.parent_map);
+}
+
+#[test]
+fn test_write_tree() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Private, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+
+    let mut out: Vec<u8> = Vec::new();
+    tree.write_tree(1, 0, &mut out).unwrap();
+    assert_eq!(out, tree.print_tree(1, 0).into_bytes());
+    assert!(String::from_utf8(out)
+        .unwrap()
+        .starts_with("- node 1 (Public)"));
+
+    // Unknown roots write nothing
+    let mut out: Vec<u8> = Vec::new();
+    tree.write_tree(99, 0, &mut out).unwrap();
+    assert!(out.is_empty());
+}
+
+#[test]
+fn test_public_view() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap();
+    tree.add_node(5, Permission::Public, ()).unwrap(); // public, but under a private node
+    tree.add_node(6, Permission::Protected, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.connect_nodes(3, 5).unwrap();
+    tree.connect_nodes(2, 6).unwrap();
+
+    let view = tree.public_view();
+    let mut ids: Vec<u32> = view.nodes.keys().copied().collect();
+    ids.sort();
+    assert_eq!(ids, vec![1, 2, 4]);
+    assert_eq!(view.get_children(1), vec![2]);
+    assert_eq!(view.get_children(2), vec![4]);
+    assert_eq!(view.parent_map.get(&4), Some(&2));
+    assert_eq!(view.validate(), Ok(()));
+
+    // The original is left alone
+    assert_eq!(tree.len(), 6);
+}
+
+#[test]
+fn test_prune_private() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Private, ()).unwrap();
+    tree.add_node(4, Permission::Public, ()).unwrap(); // goes with its private parent
+    tree.add_node(5, Permission::Protected, ()).unwrap();
+    tree.add_node(6, Permission::Private, ()).unwrap();
+    tree.add_node(7, Permission::Private, ()).unwrap(); // private root
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(1, 3).unwrap();
+    tree.connect_nodes(3, 4).unwrap();
+    tree.connect_nodes(2, 5).unwrap();
+    tree.connect_nodes(5, 6).unwrap();
+
+    assert_eq!(tree.prune_private(), 4);
+
+    let mut ids: Vec<u32> = tree.nodes.keys().copied().collect();
+    ids.sort();
+    assert_eq!(ids, vec![1, 2, 5]);
+    assert_eq!(tree.get_children(1), vec![2]);
+    assert!(tree.get_children(5).is_empty());
+    assert_eq!(tree.validate(), Ok(()));
+
+    // Nothing private is left to prune
+    assert_eq!(tree.prune_private(), 0);
+}
+
+#[test]
+fn test_merge() {
+    let build_main = || {
+        let mut tree = Tree::<u32>::new();
+        tree.add_node(1, Permission::Public, ()).unwrap(); // root node
+        tree.add_node(2, Permission::Private, ()).unwrap();
+        tree.connect_nodes(1, 2).unwrap();
+        tree.add_tag_to_node(2, "team").unwrap();
+        tree
+    };
+    let build_other = || {
+        let mut other = Tree::<u32>::new();
+        other.add_node(10, Permission::Public, ()).unwrap();
+        other.add_node(11, Permission::Public, ()).unwrap();
+        other.add_node(20, Permission::Public, ()).unwrap(); // second root
+        other.connect_nodes(10, 11).unwrap();
+        other
+    };
+
+    // Attached merge: the imported roots inherit from their new parent
+    let mut tree = build_main();
+    tree.merge(build_other(), Some(2)).unwrap();
+    assert_eq!(tree.get_children(2), vec![10, 20]);
+    assert_eq!(tree.nodes[&11].permission, Permission::Private);
+    assert!(tree.nodes[&11].effective_tags().contains("team"));
+    assert_eq!(tree.validate(), Ok(()));
+
+    // Forest merge: the imported trees stay separate
+    let mut tree = build_main();
+    tree.merge(build_other(), None).unwrap();
+    assert_eq!(tree.get_roots(), vec![1, 10, 20]);
+    assert_eq!(tree.nodes[&11].permission, Permission::Public);
+    assert_eq!(tree.validate(), Ok(()));
+
+    // Colliding IDs leave the tree unchanged
+    let mut tree = build_main();
+    let mut clash = build_other();
+    clash.add_node(2, Permission::Public, ()).unwrap();
+    assert_eq!(
+        tree.merge(clash, Some(1)),
+        Err(TreeError::NodeAlreadyExists(2))
+    );
+    assert_eq!(tree.len(), 2);
+    assert_eq!(tree.get_children(1), vec![2]);
+}
+
+#[test]
+fn test_extract_subtree() {
+    let mut tree = Tree::<u32>::new();
+
+    // Adding nodes
+    tree.add_node(1, Permission::Private, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.add_node(3, Permission::Public, ()).unwrap();
+    tree.add_node(4, Permission::Private, ()).unwrap();
+
+    // Connecting nodes
+    tree.connect_nodes(1, 2).unwrap();
+    tree.connect_nodes(2, 3).unwrap();
+    tree.connect_nodes(2, 4).unwrap();
+    tree.add_tag_to_node(1, "archive").unwrap();
+    tree.add_tag_to_node(2, "docs").unwrap();
+
+    let branch = tree.extract_subtree(2).unwrap();
+    assert_eq!(branch.get_roots(), vec![2]);
+    assert_eq!(branch.get_children(2), vec![3, 4]);
+
+    // Privacy and tags from node 1 don't come along
+    assert_eq!(branch.nodes[&2].permission, Permission::Public);
+    assert_eq!(branch.nodes[&3].permission, Permission::Public);
+    assert_eq!(branch.nodes[&4].permission, Permission::Private);
+    assert_eq!(
+        branch.nodes[&3].effective_tags(),
+        HashSet::from(["docs".to_string()])
+    );
+    assert_eq!(branch.validate(), Ok(()));
+
+    // The source tree is untouched
+    assert_eq!(tree.nodes[&3].permission, Permission::Private);
+    assert!(tree.extract_subtree(99).is_none());
+}
+
+#[test]
+fn test_exists_and_get_permission() {
+    let mut tree = Tree::<u32>::new();
+    assert!(!tree.exists(1));
+
+    // Adding nodes
+    tree.add_node(1, Permission::Private, ()).unwrap(); // root node
+    tree.add_node(2, Permission::Public, ()).unwrap();
+    tree.connect_nodes(1, 2).unwrap();
+
+    assert!(tree.exists(1));
+    assert!(tree.exists(2));
+    assert!(!tree.exists(3));
+    assert_eq!(tree.get_permission(2), Some(Permission::Private));
+    assert_eq!(tree.get_permission(3), None);
+
+    // Removed nodes are gone
+    tree.remove_node(2).unwrap();
+    assert!(!tree.exists(2));
+    assert_eq!(tree.get_permission(2), None);
+}